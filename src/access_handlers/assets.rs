@@ -0,0 +1,13 @@
+use anyhow::Result;
+use tokio;
+
+use crate::configparser::get_profile_config;
+use crate::deploy::assets::{asset_store, AssetStore};
+
+/// asset store access checks
+#[tokio::main(flavor = "current_thread")] // make this a sync function
+pub async fn check(profile_name: &str) -> Result<()> {
+    let profile = get_profile_config(profile_name)?;
+
+    asset_store(profile)?.check().await
+}
@@ -1,4 +1,4 @@
+pub mod assets;
 pub mod docker;
 pub mod frontend;
 pub mod kube;
-pub mod s3;
@@ -42,10 +42,10 @@ pub async fn extract_asset(
         // Repo file paths are relative to the challenge directory, so prepend chal dir
 
         // No action necessary, return path as-is
-        ProvideConfig::FromRepo { files } => {
+        ProvideConfig::FromRepo { files, .. } => {
             Ok(files.iter().map(|f| chal.directory.join(f)).collect_vec())
         }
-        ProvideConfig::FromRepoRename { from, to } => {
+        ProvideConfig::FromRepoRename { from, to, .. } => {
             std::fs::copy(chal.directory.join(from), chal.directory.join(to))
                 .with_context(|| format!("could not copy repo file {from:?} to {to:?}"))?;
             Ok(vec![to.clone()])
@@ -53,6 +53,7 @@ pub async fn extract_asset(
         ProvideConfig::FromRepoArchive {
             files,
             archive_name,
+            ..
         } => {
             zip_files(
                 &chal.directory.join(archive_name),
@@ -94,6 +95,7 @@ pub async fn extract_asset(
                 ProvideConfig::FromContainer {
                     container: container_name,
                     files,
+                    ..
                 } => extract_files(chal, &container, files)
                     .await
                     .with_context(|| {
@@ -104,6 +106,7 @@ pub async fn extract_asset(
                     container: container_name,
                     from,
                     to,
+                    ..
                 } => extract_rename(chal, &container, from, &chal.directory.join(to))
                     .await
                     .with_context(|| {
@@ -114,6 +117,7 @@ pub async fn extract_asset(
                     container: container_name,
                     files,
                     archive_name,
+                    ..
                 } => extract_archive(chal, &container, files, &chal.directory.join(archive_name))
                     .await
                     .with_context(|| {
@@ -121,6 +121,26 @@ pub async fn push_image(image_tag: &str, creds: &UserPass) -> Result<String> {
     Ok(tag.to_string())
 }
 
+/// Return the pinned `repo@sha256:...` reference for a pushed image tag, if the
+/// daemon knows the digest for it.
+pub async fn image_digest(image_tag: &str) -> Result<Option<String>> {
+    trace!("looking up digest for {image_tag:?}");
+    let client = docker().await?;
+
+    let (repo, _tag) = image_tag
+        .rsplit_once(":")
+        .context("failed to get tag from full image string")?;
+
+    let image = client.inspect_image(image_tag).await?;
+
+    // images can have digests for multiple repos, find the one we pushed to
+    Ok(image
+        .repo_digests
+        .unwrap_or_default()
+        .into_iter()
+        .find(|d| d.split_once('@').is_some_and(|(r, _)| r == repo)))
+}
+
 pub async fn create_container(image_tag: &str, name: &str) -> Result<ContainerInfo> {
     debug!("creating container {name:?} from image {image_tag:?}");
     let client = docker().await?;
@@ -15,7 +15,7 @@ use crate::configparser::challenge::{
     BuildObject, ChallengeConfig, ImageSource::*, Pod, ProvideConfig,
 };
 use crate::configparser::{enabled_challenges, get_config};
-use crate::utils::TryJoinAll;
+use crate::utils::{file_sha256, TryJoinAll};
 
 pub mod artifacts;
 pub mod docker;
@@ -26,9 +26,26 @@ pub struct BuildResult {
     /// Container image tags of all containers in the challenge, if the challenge has container images.
     /// Will be empty if challenge has no images built from source.
     pub tags: Vec<TagWithSource>,
-    /// Path on disk to local assets (both built and static).
+    /// Local assets (both built and static).
     /// Will be empty if challenge has no file assets
-    pub assets: Vec<PathBuf>,
+    pub assets: Vec<Asset>,
+}
+
+/// A file provided to players.
+#[derive(Debug, Clone)]
+pub struct Asset {
+    /// Path on disk, or path in the asset bucket once uploaded
+    pub path: PathBuf,
+    /// SHA-256 of the file contents, as hex
+    pub sha256: String,
+    /// Content-Type override from the challenge's `provide`
+    pub content_type: Option<String>,
+    /// Content-Disposition override from the challenge's `provide`
+    pub content_disposition: Option<String>,
+    /// Only let players download this through expiring signed urls
+    pub private: bool,
+    /// Where players can download this from, once uploaded
+    pub url: Option<String>,
 }
 
 /// Tag string with added context of where it came from (built locally or an upstream image)
@@ -70,22 +87,44 @@ async fn build_challenge(
         assets: vec![],
     };
 
-    built.tags = chal
+    // main and additional containers for every pod, with the tag each should have
+    let images = chal
         .pods
         .iter()
-        .map(|p| async {
-            match &p.image_source {
+        .map(|p| {
+            let main = (
+                p.name.clone(),
+                &p.image_source,
+                chal.container_tag_for_pod(profile_name, &p.name)?,
+            );
+            let sidecars = p
+                .sidecars()
+                .map(|c| {
+                    Ok((
+                        format!("{}-{}", p.name, c.name),
+                        &c.image_source,
+                        chal.container_tag_for_sidecar(profile_name, &p.name, &c.name)?,
+                    ))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            Ok(std::iter::once(main).chain(sidecars).collect_vec())
+        })
+        .flatten_ok()
+        .collect::<Result<Vec<_>>>()?;
+
+    built.tags = images
+        .iter()
+        .map(|(name, source, tag)| async move {
+            match source {
                 Image(tag) => Ok(TagWithSource::Upstream(tag.to_string())),
-                // build any pods that need building
+                // build any containers that need building
                 Build(build) => {
-                    let tag = chal.container_tag_for_pod(profile_name, &p.name)?;
-
-                    let res = docker::build_image(&chal.directory, build, &tag)
+                    let res = docker::build_image(&chal.directory, build, tag)
                         .await
                         .with_context(|| {
                             format!(
                                 "error building image {} for chal {}",
-                                p.name,
+                                name,
                                 chal.directory.to_string_lossy()
                             )
                         });
@@ -134,6 +173,7 @@ async fn build_challenge(
             .provide
             .iter()
             .map(|p| async {
+                let (content_type, content_disposition) = p.upload_headers();
                 artifacts::extract_asset(chal, p, profile_name)
                     .await
                     .with_context(|| {
@@ -142,6 +182,21 @@ async fn build_challenge(
                             chal.directory,
                         )
                     })
+                    .and_then(|paths| {
+                        paths
+                            .into_iter()
+                            .map(|path| {
+                                Ok(Asset {
+                                    sha256: file_sha256(&path)?,
+                                    path,
+                                    content_type: content_type.map(String::from),
+                                    content_disposition: content_disposition.map(String::from),
+                                    private: p.is_private(),
+                                    url: None,
+                                })
+                            })
+                            .collect::<Result<Vec<_>>>()
+                    })
             })
             .try_join_all()
             .await?
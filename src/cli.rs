@@ -1,3 +1,5 @@
+use std::path::PathBuf;
+
 use clap::{Parser, Subcommand};
 
 #[derive(Parser, Debug)]
@@ -54,6 +56,88 @@ pub enum Commands {
         /// Test changes without actually applying
         #[arg(short = 'n', long)]
         dry_run: bool,
+
+        /// Remove any existing deploy lock before deploying, even if another
+        /// deploy holds it
+        #[arg(long)]
+        force_unlock: bool,
+
+        /// Also remove challenges from the frontend that are no longer enabled
+        /// in the profile
+        #[arg(long)]
+        prune_frontend: bool,
+    },
+
+    /// Manage per-team instances of instanced challenges.
+    Instance {
+        #[command(subcommand)]
+        command: InstanceCommands,
+    },
+
+    /// Roll back a challenge to a previous deployed revision, without
+    /// rebuilding any images.
+    Rollback {
+        /// Deployment profile
+        #[arg(short, long, value_name = "PROFILE")]
+        profile: String,
+
+        /// Challenge to roll back, as category/name
+        #[arg(short, long, value_name = "CHALLENGE")]
+        chal: String,
+
+        /// Revision to roll back to (default: the previous revision)
+        #[arg(long, value_name = "REV")]
+        to: Option<u64>,
+    },
+
+    /// Render Kubernetes manifests for enabled challenges to disk instead of
+    /// applying them, e.g. for GitOps tools like Argo CD.
+    ///
+    /// Manifests are written to one directory per challenge.
+    Render {
+        /// Deployment profile
+        #[arg(short, long, value_name = "PROFILE")]
+        profile: String,
+
+        /// Directory to write rendered manifests to
+        #[arg(short, long, value_name = "DIR")]
+        out: PathBuf,
+
+        /// Also generate kustomization.yaml files for the rendered manifests
+        #[arg(long)]
+        kustomize: bool,
+
+        /// Also write manifests containing secret values, like env Secrets.
+        /// These are skipped by default so they do not end up committed
+        #[arg(long)]
+        include_secrets: bool,
+    },
+
+    /// Re-sign download urls for private assets and update the frontend with
+    /// them, without rebuilding or redeploying anything.
+    ///
+    /// Signed urls expire after `s3.private_url_expiry`, so run this more
+    /// often than that, e.g. from a cron job.
+    RefreshUrls {
+        /// Deployment profile
+        #[arg(short, long, value_name = "PROFILE")]
+        profile: String,
+    },
+
+    /// Remove all deployed challenges, frontend challenges, and uploaded assets
+    /// for a profile.
+    Teardown {
+        /// Deployment profile
+        #[arg(short, long, value_name = "PROFILE")]
+        profile: String,
+
+        /// Also uninstall the charts deployed by cluster-setup
+        #[arg(long)]
+        uninstall_charts: bool,
+
+        /// Do not ask for confirmation
+        #[arg(short, long)]
+        yes: bool,
     },
 
     /// Validate contents of rcds.yaml and any challenge.yaml files.
@@ -77,7 +161,7 @@ pub enum Commands {
         #[arg(short, long)]
         registry: bool,
 
-        #[arg(short, long, help = "Check S3 asset bucket access and permissions")]
+        #[arg(short, long, help = "Check asset store access and permissions")]
         bucket: bool,
     },
 
@@ -87,4 +171,59 @@ pub enum Commands {
         #[arg(short, long, value_name = "PROFILE")]
         profile: String,
     },
+
+    /// Create the asset bucket if needed and allow public downloads of
+    /// challenge assets from it
+    BucketSetup {
+        /// Deployment profile to use
+        #[arg(short, long, value_name = "PROFILE")]
+        profile: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum InstanceCommands {
+    /// Create a new instance of a challenge for a team
+    Create {
+        /// Deployment profile
+        #[arg(short, long, value_name = "PROFILE")]
+        profile: String,
+
+        /// Challenge to create an instance of, as category/name
+        #[arg(short, long, value_name = "CHALLENGE")]
+        chal: String,
+
+        /// Team the instance is for
+        #[arg(short, long, value_name = "TEAM")]
+        team: String,
+    },
+
+    /// Remove an instance before it expires
+    Delete {
+        /// Deployment profile
+        #[arg(short, long, value_name = "PROFILE")]
+        profile: String,
+
+        /// Challenge the instance belongs to, as category/name
+        #[arg(short, long, value_name = "CHALLENGE")]
+        chal: String,
+
+        /// Instance id
+        #[arg(long, value_name = "ID")]
+        id: String,
+    },
+
+    /// List all running instances
+    List {
+        /// Deployment profile
+        #[arg(short, long, value_name = "PROFILE")]
+        profile: String,
+    },
+
+    /// Remove all expired instances
+    Gc {
+        /// Deployment profile
+        #[arg(short, long, value_name = "PROFILE")]
+        profile: String,
+    },
 }
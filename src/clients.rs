@@ -1,12 +1,14 @@
 // Builders for the various client structs for Docker/Kube etc.
 
-use std::sync::OnceLock;
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex, OnceLock};
 
 use anyhow::{anyhow, bail, Context, Error, Result};
 use bollard;
 use futures::TryFutureExt;
 use k8s_openapi::api::{
     apps::v1::Deployment,
+    autoscaling::v2::HorizontalPodAutoscaler,
     core::v1::{Pod, Service},
     networking::v1::Ingress,
 };
@@ -73,34 +75,45 @@ pub async fn engine_type() -> EngineType {
 // S3 stuff
 //
 
-// this does need to be a OnceLock instead of a LazyLock, even though how this
-// is used is more inline with a LazyLock. Lazy does not allow for passing
-// anything into the init function, and this needs a parameter to know what
-// profile to fetch creds for.
-static BUCKET_CLIENT: OnceLock<Box<s3::Bucket>> = OnceLock::new();
+// keyed by the connection settings instead of the profile name, since that is
+// all the bucket is built from. clients are leaked to hand out 'static refs
+// like the other cached clients; there are only ever as many as profiles.
+type BucketKey = (String, String, String, String, String);
+static BUCKET_CLIENTS: LazyLock<Mutex<HashMap<BucketKey, &'static s3::Bucket>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
 
 /// return existing or create new bucket client for passed profile config
-pub fn bucket_client(config: &config::S3Config) -> Result<&s3::Bucket> {
-    match BUCKET_CLIENT.get() {
-        Some(b) => Ok(b),
-        None => {
-            trace!("creating bucket client");
-            let region = s3::Region::Custom {
-                region: config.region.clone(),
-                endpoint: config.endpoint.clone(),
-            };
-            let creds = s3::creds::Credentials::new(
-                Some(&config.access_key),
-                Some(&config.secret_key),
-                None,
-                None,
-                None,
-            )?;
-            let bucket = s3::Bucket::new(&config.bucket_name, region, creds)?.with_path_style();
-
-            Ok(BUCKET_CLIENT.get_or_init(|| bucket))
-        }
+pub fn bucket_client(config: &config::S3Config) -> Result<&'static s3::Bucket> {
+    let key = (
+        config.bucket_name.clone(),
+        config.endpoint.clone(),
+        config.region.clone(),
+        config.access_key.clone(),
+        config.secret_key.clone(),
+    );
+
+    let mut clients = BUCKET_CLIENTS.lock().unwrap();
+    if let Some(b) = clients.get(&key) {
+        return Ok(b);
     }
+
+    trace!("creating bucket client for {:?}", config.bucket_name);
+    let region = s3::Region::Custom {
+        region: config.region.clone(),
+        endpoint: config.endpoint.clone(),
+    };
+    let creds = s3::creds::Credentials::new(
+        Some(&config.access_key),
+        Some(&config.secret_key),
+        None,
+        None,
+        None,
+    )?;
+    let bucket = s3::Bucket::new(&config.bucket_name, region, creds)?.with_path_style();
+
+    let bucket: &'static s3::Bucket = Box::leak(bucket);
+    clients.insert(key, bucket);
+    Ok(bucket)
 }
 
 /// create public/anonymous bucket client for passed profile config
@@ -252,7 +265,7 @@ pub async fn apply_manifest_yaml(
 }
 
 /// Deserialize multi-document yaml string into a Vec of the documents
-fn multidoc_deserialize(data: &str) -> Result<Vec<serde_yml::Value>> {
+pub fn multidoc_deserialize(data: &str) -> Result<Vec<serde_yml::Value>> {
     use serde::Deserialize;
 
     let mut docs = vec![];
@@ -340,6 +353,30 @@ pub async fn wait_for_status(client: &kube::Client, object: &DynamicObject) -> R
             .await?;
         }
 
+        // wait for HorizontalPodAutoscaler to scale its deployment up to the
+        // minimum, and for those replicas to become ready
+        "HorizontalPodAutoscaler" => {
+            let api: kube::Api<HorizontalPodAutoscaler> =
+                kube::Api::namespaced(client.clone(), &object.namespace().unwrap());
+            let hpa = await_condition(
+                api,
+                &object.name_any(),
+                |hpa: Option<&HorizontalPodAutoscaler>| {
+                    hpa.is_some_and(|h| {
+                        let min = h.spec.as_ref().and_then(|s| s.min_replicas).unwrap_or(1);
+                        let current = h.status.as_ref().and_then(|s| s.current_replicas);
+                        current.unwrap_or(0) >= min
+                    })
+                },
+            )
+            .await?;
+
+            let target = hpa.unwrap().spec.unwrap_or_default().scale_target_ref;
+            let api: kube::Api<Deployment> =
+                kube::Api::namespaced(client.clone(), &object.namespace().unwrap());
+            await_condition(api, &target.name, conditions::is_deployment_completed()).await?;
+        }
+
         other => trace!("not checking status for resource type {other}"),
     };
 
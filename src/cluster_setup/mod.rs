@@ -98,6 +98,17 @@ pub async fn install_extdns(profile: &config::ProfileConfig) -> Result<()> {
     )
 }
 
+/// Remove all charts installed by cluster setup, in reverse install order.
+pub async fn uninstall_charts(profile: &config::ProfileConfig) -> Result<()> {
+    for release in ["external-dns", "cert-manager", "ingress-nginx"] {
+        info!("uninstalling {release} chart...");
+        uninstall_helm_chart(profile, release, INGRESS_NAMESPACE)
+            .with_context(|| format!("failed to uninstall {release} helm chart"))?;
+    }
+
+    Ok(())
+}
+
 //
 // install helpers
 //
@@ -145,6 +156,34 @@ fn install_helm_chart(
         profile.kubecontext
     );
 
+    run_helm(profile, &args)
+}
+
+/// Uninstall the release via shelling out to Helm cli
+fn uninstall_helm_chart(
+    profile: &config::ProfileConfig,
+    release_name: &str,
+    namespace: &str,
+) -> Result<()> {
+    // dont error if the release was never installed
+    let args = format!(
+        r#"
+        uninstall
+            {release_name}
+            --namespace {namespace}
+            --ignore-not-found
+            --wait --timeout 5m
+            --debug
+            --kube-context {}
+        "#,
+        profile.kubecontext
+    );
+
+    run_helm(profile, &args)
+}
+
+/// Run helm with the given whitespace-separated args, logging its output
+fn run_helm(profile: &config::ProfileConfig, args: &str) -> Result<()> {
     let mut helm_cmd = duct::cmd("helm", args.split_whitespace())
         // capture stdout and stderr for our logging
         .stderr_to_stdout()
@@ -0,0 +1,33 @@
+use std::process::exit;
+use tracing::{error, info};
+
+use crate::configparser::get_profile_config;
+use crate::deploy::assets::{asset_store, Store};
+
+#[tokio::main(flavor = "current_thread")] // make this a sync function
+pub async fn run(profile_name: &str) {
+    let profile = get_profile_config(profile_name).unwrap();
+
+    let store = match asset_store(profile) {
+        Ok(Store::S3(s)) => s,
+        Ok(other) => {
+            info!(
+                "assets are stored in {}, no bucket to set up",
+                other.describe()
+            );
+            return;
+        }
+        Err(e) => {
+            error!("{e:?}");
+            exit(1);
+        }
+    };
+
+    info!("setting up bucket...");
+    if let Err(e) = store.setup(&profile.frontend_url).await {
+        error!("{e:?}");
+        exit(1);
+    }
+
+    info!("bucket ready!")
+}
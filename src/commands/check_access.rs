@@ -83,9 +83,9 @@ fn check_profile(
         };
     }
     if bucket {
-        match access::s3::check(name).context("could not access asset bucket") {
+        match access::assets::check(name).context("could not access asset store") {
             Err(e) => errs.push(e),
-            Ok(_) => info!("  bucket ok!"),
+            Ok(_) => info!("  assets ok!"),
         };
     }
 
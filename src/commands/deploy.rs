@@ -1,3 +1,4 @@
+use anyhow::Result;
 use itertools::Itertools;
 use std::process::exit;
 use tracing::{debug, error, info, trace, warn};
@@ -5,17 +6,60 @@ use tracing::{debug, error, info, trace, warn};
 use crate::builder::build_challenges;
 use crate::configparser::{get_config, get_profile_config};
 use crate::deploy;
+use crate::deploy::lock::DeployLock;
 
 #[tokio::main(flavor = "current_thread")] // make this a sync function
-pub async fn run(profile_name: &str, no_build: &bool, _dry_run: &bool) {
+pub async fn run(
+    profile_name: &str,
+    no_build: &bool,
+    dry_run: &bool,
+    force_unlock: &bool,
+    prune_frontend: &bool,
+) {
     let profile = get_profile_config(profile_name).unwrap();
 
+    if *dry_run {
+        if let Err(e) = dry_run_profile(profile_name).await {
+            error!("{e:?}");
+            exit(1);
+        }
+        return;
+    }
+
     // has the cluster been setup?
     if let Err(e) = deploy::check_setup(profile).await {
         error!("{e:?}");
         exit(1);
     }
 
+    // make sure no one else is deploying at the same time
+    let lock = match deploy::lock::acquire(profile, *force_unlock).await {
+        Ok(l) => l,
+        Err(e) => {
+            error!("{:?}", e.context("could not acquire deploy lock"));
+            exit(1);
+        }
+    };
+
+    let result = deploy_profile(profile_name, no_build, *prune_frontend, &lock).await;
+
+    // always release the lock, even if the deploy failed
+    if let Err(e) = lock.release().await {
+        warn!("could not release deploy lock: {e:?}");
+    }
+
+    if let Err(e) = result {
+        error!("{e:?}");
+        exit(1);
+    }
+}
+
+async fn deploy_profile(
+    profile_name: &str,
+    no_build: &bool,
+    prune_frontend: bool,
+    lock: &DeployLock,
+) -> Result<()> {
     // build before deploying
     if *no_build {
         warn!("");
@@ -24,13 +68,7 @@ pub async fn run(profile_name: &str, no_build: &bool, _dry_run: &bool) {
     }
 
     info!("building challenges...");
-    let build_results = match build_challenges(profile_name, true, true).await {
-        Ok(result) => result,
-        Err(e) => {
-            error!("{e:?}");
-            exit(1);
-        }
-    };
+    let build_results = build_challenges(profile_name, true, true).await?;
 
     trace!(
         "got built results: {:#?}",
@@ -42,25 +80,39 @@ pub async fn run(profile_name: &str, no_build: &bool, _dry_run: &bool) {
     //    - namespace, deployment, service, ingress
     //    - upgrade ingress config with new listen ports
     //
-    // B) upload asset files to bucket
+    // B) upload asset files to asset store
     //
     // C) update frontend with new state of challenges
 
     // A)
-    if let Err(e) = deploy::kubernetes::deploy_challenges(profile_name, &build_results).await {
-        error!("{e:?}");
-        exit(1);
-    }
+    deploy::kubernetes::deploy_challenges(profile_name, &build_results, lock).await?;
 
     // B)
-    if let Err(e) = deploy::s3::upload_assets(profile_name, &build_results).await {
-        error!("{e:?}");
-        exit(1);
-    }
+    lock.check()?;
+    let uploaded = deploy::assets::upload_assets(profile_name, &build_results).await?;
+    deploy::assets::remove_stale_assets(profile_name, &build_results, false).await?;
 
     // C)
-    if let Err(e) = deploy::frontend::update_frontend(profile_name, &build_results).await {
-        error!("{e:?}");
-        exit(1);
-    }
+    lock.check()?;
+    // frontend needs the uploaded asset paths, not the local ones
+    let uploaded_assets = build_results
+        .iter()
+        .map(|(chal, _)| *chal)
+        .zip(uploaded.into_iter().map(|r| r.assets))
+        .collect_vec();
+    deploy::frontend::update_frontend(profile_name, &uploaded_assets, prune_frontend).await?;
+
+    Ok(())
+}
+
+/// Show what a deploy would change, without changing anything.
+async fn dry_run_profile(profile_name: &str) -> Result<()> {
+    info!("building challenges...");
+    let build_results = build_challenges(profile_name, false, true).await?;
+
+    info!("checking for stale assets...");
+    let stale = deploy::assets::remove_stale_assets(profile_name, &build_results, true).await?;
+    info!("dry run: would remove {} stale assets", stale.len());
+
+    Ok(())
 }
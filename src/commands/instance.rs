@@ -0,0 +1,65 @@
+use std::process::exit;
+use tracing::{debug, error, info, trace, warn};
+
+use crate::instancer;
+
+#[tokio::main(flavor = "current_thread")] // make this a sync function
+pub async fn create(profile_name: &str, chal: &str, team: &str) {
+    match instancer::create_instance(profile_name, chal, team).await {
+        Ok(info) => {
+            info!(
+                "created instance {} (expires {})",
+                info.instance.id, info.instance.expires_at
+            );
+            for endpoint in info.endpoints {
+                info!("  {endpoint}");
+            }
+        }
+        Err(e) => {
+            error!("{e:?}");
+            exit(1);
+        }
+    }
+}
+
+#[tokio::main(flavor = "current_thread")] // make this a sync function
+pub async fn delete(profile_name: &str, chal: &str, id: &str) {
+    match instancer::delete_instance(profile_name, chal, id).await {
+        Ok(_) => info!("removed instance {id}"),
+        Err(e) => {
+            error!("{e:?}");
+            exit(1);
+        }
+    }
+}
+
+#[tokio::main(flavor = "current_thread")] // make this a sync function
+pub async fn list(profile_name: &str) {
+    match instancer::list_instances(profile_name).await {
+        Ok(instances) => {
+            info!("{} running instances", instances.len());
+            for info in instances {
+                info!(
+                    "  {} {}: team {:?}, expires {}",
+                    info.namespace, info.instance.id, info.instance.team, info.instance.expires_at
+                );
+            }
+        }
+        Err(e) => {
+            error!("{e:?}");
+            exit(1);
+        }
+    }
+}
+
+#[tokio::main(flavor = "current_thread")] // make this a sync function
+pub async fn gc(profile_name: &str) {
+    info!("removing expired instances...");
+    match instancer::collect_garbage(profile_name).await {
+        Ok(removed) => info!("removed {} expired instances", removed.len()),
+        Err(e) => {
+            error!("{e:?}");
+            exit(1);
+        }
+    }
+}
@@ -1,7 +1,13 @@
+pub mod bucket_setup;
 pub mod build;
 pub mod check_access;
 pub mod cluster_setup;
 pub mod deploy;
+pub mod instance;
+pub mod refresh_urls;
+pub mod render;
+pub mod rollback;
+pub mod teardown;
 pub mod validate;
 
 // These modules should not do much and act mostly as a thunk to handle
@@ -0,0 +1,23 @@
+use std::process::exit;
+use tracing::{error, info};
+
+use crate::deploy;
+
+#[tokio::main(flavor = "current_thread")] // make this a sync function
+pub async fn run(profile_name: &str) {
+    info!("refreshing asset urls...");
+    let refreshed = match deploy::assets::refresh_urls(profile_name).await {
+        Ok(r) => r,
+        Err(e) => {
+            error!("{e:?}");
+            exit(1);
+        }
+    };
+
+    if let Err(e) = deploy::frontend::update_frontend(profile_name, &refreshed, false).await {
+        error!("{e:?}");
+        exit(1);
+    }
+
+    info!("asset urls refreshed!")
+}
@@ -0,0 +1,25 @@
+use std::path::Path;
+use std::process::exit;
+use tracing::{debug, error, info, trace, warn};
+
+use crate::deploy;
+
+pub fn run(profile_name: &str, out: &Path, kustomize: &bool, include_secrets: &bool) {
+    info!("rendering challenge manifests to {out:?}...");
+
+    match deploy::kubernetes::write_rendered_challenges(
+        profile_name,
+        out,
+        *kustomize,
+        *include_secrets,
+    ) {
+        Ok(written) => {
+            debug!("wrote files: {written:#?}");
+            info!("rendered {} files", written.len());
+        }
+        Err(e) => {
+            error!("{e:?}");
+            exit(1);
+        }
+    }
+}
@@ -0,0 +1,51 @@
+use std::path::Path;
+use std::process::exit;
+use tracing::{debug, error, info, trace, warn};
+
+use crate::configparser::{get_challenges, get_profile_config};
+use crate::deploy;
+
+#[tokio::main(flavor = "current_thread")] // make this a sync function
+pub async fn run(profile_name: &str, chal_path: &str, to: &Option<u64>) {
+    let chal = match get_challenges()
+        .unwrap()
+        .iter()
+        .find(|c| c.directory == Path::new(chal_path))
+    {
+        Some(c) => c,
+        None => {
+            error!("challenge {chal_path:?} not found");
+            exit(1);
+        }
+    };
+
+    // rollbacks apply manifests too, so they must not overlap with a deploy
+    let profile = get_profile_config(profile_name).unwrap();
+    let lock = match deploy::lock::acquire(profile, false).await {
+        Ok(l) => l,
+        Err(e) => {
+            error!("{:?}", e.context("could not acquire deploy lock"));
+            exit(1);
+        }
+    };
+
+    info!("rolling back challenge...");
+    let result =
+        deploy::kubernetes::history::rollback_challenge(profile_name, chal, *to, &lock).await;
+
+    // always release the lock, even if the rollback failed
+    if let Err(e) = lock.release().await {
+        warn!("could not release deploy lock: {e:?}");
+    }
+
+    match result {
+        Ok(rev) => info!(
+            "rolled back {chal_path:?}, now at revision {}",
+            rev.revision
+        ),
+        Err(e) => {
+            error!("{e:?}");
+            exit(1);
+        }
+    }
+}
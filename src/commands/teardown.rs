@@ -0,0 +1,116 @@
+use std::io::{stdin, stdout, IsTerminal, Write};
+use std::process::exit;
+
+use anyhow::{bail, Result};
+use tracing::{debug, error, info, trace, warn};
+
+use crate::cluster_setup as setup;
+use crate::configparser::get_profile_config;
+use crate::deploy;
+
+#[tokio::main(flavor = "current_thread")] // make this a sync function
+pub async fn run(profile_name: &str, uninstall_charts: &bool, yes: &bool) {
+    let profile = get_profile_config(profile_name).unwrap();
+
+    // dont let a deploy recreate things while we are removing them
+    let lock = match deploy::lock::acquire(profile, false).await {
+        Ok(l) => l,
+        Err(e) => {
+            error!("{:?}", e.context("could not acquire deploy lock"));
+            exit(1);
+        }
+    };
+
+    let result = teardown_profile(profile_name, *uninstall_charts, *yes).await;
+
+    // the lock lives in the ingress namespace, which is kept around even if
+    // the charts in it were uninstalled
+    if let Err(e) = lock.release().await {
+        warn!("could not release deploy lock: {e:?}");
+    }
+
+    if let Err(e) = result {
+        error!("{e:?}");
+        exit(1);
+    }
+}
+
+async fn teardown_profile(profile_name: &str, uninstall_charts: bool, yes: bool) -> Result<()> {
+    let profile = get_profile_config(profile_name)?;
+
+    // show what is about to be removed before asking
+    let namespaces = deploy::teardown::managed_namespaces(profile).await?;
+    let assets = deploy::teardown::uploaded_assets(profile).await?;
+    // the frontend may already be gone; that should not block cleaning up
+    // the cluster and bucket
+    let frontend_challenges = match deploy::teardown::frontend_challenges(profile).await {
+        Ok(chals) => Some(chals),
+        Err(e) => {
+            warn!("could not list challenges on frontend, skipping frontend cleanup: {e:?}");
+            None
+        }
+    };
+
+    warn!("this will permanently remove from profile {profile_name:?}:");
+    warn!("  {} challenge namespaces:", namespaces.len());
+    for ns in namespaces.iter() {
+        warn!("    {ns}");
+    }
+    warn!(
+        "  {} uploaded asset files in {}",
+        assets.len(),
+        deploy::assets::asset_store(profile)?.describe()
+    );
+    if let Some(chals) = &frontend_challenges {
+        warn!(
+            "  {} challenges on frontend {}",
+            chals.len(),
+            profile.frontend_url
+        );
+    }
+    if uninstall_charts {
+        warn!("  cluster setup charts (ingress-nginx, cert-manager, external-dns)");
+    }
+
+    if !yes && !confirm()? {
+        info!("aborted, nothing was removed");
+        return Ok(());
+    }
+
+    // take challenges off the frontend first so players do not see them break
+    if frontend_challenges.is_some() {
+        info!("removing challenges from frontend...");
+        match deploy::teardown::delete_frontend_challenges(profile).await {
+            Ok(removed) => info!("  removed {} challenges", removed.len()),
+            Err(e) => warn!("could not remove challenges from frontend, skipping: {e:?}"),
+        }
+    }
+
+    info!("deleting challenge namespaces...");
+    deploy::teardown::delete_challenge_namespaces(profile).await?;
+
+    info!("deleting uploaded assets...");
+    let deleted = deploy::teardown::delete_assets(profile).await?;
+    info!("  deleted {} files", deleted.len());
+
+    if uninstall_charts {
+        setup::uninstall_charts(profile).await?;
+    }
+
+    info!("teardown complete!");
+    Ok(())
+}
+
+/// Ask for interactive confirmation on stdin.
+fn confirm() -> Result<bool> {
+    if !stdin().is_terminal() {
+        bail!("refusing to tear down without confirmation; pass --yes to run non-interactively");
+    }
+
+    print!("continue? [y/N] ");
+    stdout().flush()?;
+
+    let mut answer = String::new();
+    stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
@@ -2,7 +2,7 @@ use std::path::Path;
 use std::process::exit;
 use tracing::{debug, error, info, trace, warn};
 
-use crate::configparser::{get_challenges, get_config, get_profile_deploy};
+use crate::configparser::{enabled_challenges, get_challenges, get_config, get_profile_deploy};
 
 pub fn run() {
     info!("validating config...");
@@ -55,5 +55,45 @@ pub fn run() {
             exit(1)
         }
     }
-    info!("  deploy ok!")
+    info!("  deploy ok!");
+
+    // check enabled challenges fit in the profile resource budget
+    info!("validating resource budgets...");
+    for (profile_name, pconfig) in config.profiles.iter() {
+        let budget = match &pconfig.challenge_budget {
+            Some(b) => b,
+            None => continue,
+        };
+        let budget_memory = match budget.memory_bytes() {
+            Ok(m) => m,
+            Err(err) => {
+                error!("invalid challenge budget for profile '{profile_name}': {err:#}");
+                exit(1);
+            }
+        };
+
+        let mut over_budget = vec![];
+        for chal in enabled_challenges(profile_name).unwrap() {
+            match chal.total_resources() {
+                Ok((cpu, memory)) if cpu > budget.cpu * 1000 || memory > budget_memory => {
+                    over_budget.push(format!(
+                        "{:?} requests {cpu}m cpu, {}Mi memory",
+                        chal.directory,
+                        memory / 1024 / 1024
+                    ))
+                }
+                Ok(_) => (),
+                Err(err) => over_budget.push(format!("{:?}: {err:#}", chal.directory)),
+            }
+        }
+        if !over_budget.is_empty() {
+            error!(
+                "Challenges in profile '{profile_name}' exceed the budget of {} cpu, {} memory:",
+                budget.cpu, budget.memory
+            );
+            over_budget.iter().for_each(|c| error!("  - {c}"));
+            exit(1)
+        }
+    }
+    info!("  resources ok!")
 }
@@ -1,4 +1,4 @@
-use anyhow::{anyhow, Context, Error, Result};
+use anyhow::{anyhow, bail, Context, Error, Result};
 use figment::providers::{Env, Format, Serialized, Yaml};
 use figment::Figment;
 use fully_pub::fully_pub;
@@ -7,13 +7,14 @@ use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 use serde_nested_with::serde_nested;
 use std::collections::HashMap as Map;
+use std::fs;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use tracing::{debug, error, info, trace, warn};
 use void::Void;
 
 use crate::clients::render_strict;
-use crate::configparser::config::Resource;
+use crate::configparser::config::{Resource, Scheduling};
 use crate::configparser::field_coersion::string_or_struct;
 use crate::configparser::get_config;
 
@@ -77,32 +78,88 @@ pub fn parse_one(path: &PathBuf) -> Result<ChallengeConfig> {
     // coerce pod env lists to maps
     // TODO: do this in serde deserialize?
     for pod in parsed.pods.iter_mut() {
-        pod.env = match pod.env.clone() {
-            ListOrMap::Map(m) => ListOrMap::Map(m),
-            ListOrMap::List(l) => {
-                // split NAME=VALUE list into separate name and value
-                let split: Vec<(String, String)> = l
-                    .into_iter()
-                    .map(|var| {
-                        // error if envvar is malformed
-                        let split = var.splitn(2, '=').collect_vec();
-                        if split.len() == 2 {
-                            Ok((split[0].to_string(), split[1].to_string()))
-                        } else {
-                            Err(anyhow!("Cannot split envvar {var:?}"))
-                        }
-                    })
-                    .collect::<Result<_>>()?;
-                // build hashmap from split name and value iteratively. this
-                // can't use HashMap::from() here since the values are dynamic
-                // and from() only works for Vec constants
-                let map = split
-                    .into_iter()
-                    .fold(Map::new(), |mut map, (name, value)| {
-                        map.insert(name, value);
-                        map
-                    });
-                ListOrMap::Map(map)
+        pod.env = env_to_map(pod.env.clone())?;
+        for container in pod
+            .containers
+            .iter_mut()
+            .chain(pod.init_containers.iter_mut())
+        {
+            container.env = env_to_map(container.env.clone())?;
+        }
+    }
+
+    for pod in parsed.pods.iter() {
+        if let Some(autoscale) = &pod.autoscale {
+            if autoscale.min < 1 || autoscale.min > autoscale.max {
+                bail!(
+                    "pod {:?} autoscale min must be at least 1 and no more than max",
+                    pod.name
+                );
+            }
+        }
+
+        if !pod.command.is_empty() && !matches!(pod.image_source, ImageSource::Image(_)) {
+            bail!(
+                "pod {:?} can only override command when using an image, set the entrypoint in the Dockerfile instead",
+                pod.name
+            );
+        }
+        if pod.workdir.as_ref().is_some_and(|w| !w.starts_with('/')) {
+            bail!("pod {:?} workdir must be absolute", pod.name);
+        }
+
+        let all_env = std::iter::once(&pod.env).chain(pod.sidecars().map(|c| &c.env));
+        for env in all_env {
+            if let ListOrMap::Map(vars) = env {
+                if let Some((name, _)) = vars
+                    .iter()
+                    .find(|(_, v)| **v == EnvValue::Flag { flag: false })
+                {
+                    bail!(
+                        "pod {:?} env var {name:?} has `flag: false`, remove it or set a value",
+                        pod.name
+                    );
+                }
+            }
+        }
+
+        // all containers in a pod share the same namespace for names
+        if let Some(dupe) = std::iter::once(&pod.name)
+            .chain(pod.sidecars().map(|c| &c.name))
+            .duplicates()
+            .next()
+        {
+            bail!("pod {:?} has multiple containers named {dupe:?}", pod.name);
+        }
+        if let Some(init) = pod.init_containers.iter().find(|c| !c.ports.is_empty()) {
+            bail!(
+                "pod {:?} init container {:?} cannot have ports",
+                pod.name,
+                init.name
+            );
+        }
+
+        if let Some(dupe) = pod.volumes.iter().map(|v| &v.name).duplicates().next() {
+            bail!("pod {:?} has multiple volumes named {dupe:?}", pod.name);
+        }
+        for volume in pod.volumes.iter() {
+            if !volume.path.starts_with('/') {
+                bail!(
+                    "pod {:?} volume {:?} path must be absolute",
+                    pod.name,
+                    volume.name
+                );
+            }
+            // single-node volumes cannot be shared between replicas, which
+            // may be scheduled on different nodes
+            if let VolumeKind::Persistent { access_mode, .. } = &volume.kind {
+                if access_mode.starts_with("ReadWriteOnce") && pod.max_replicas() > 1 {
+                    bail!(
+                        "pod {:?} volume {:?} is {access_mode} but the pod has more than one replica",
+                        pod.name,
+                        volume.name
+                    );
+                }
             }
         }
     }
@@ -112,6 +169,38 @@ pub fn parse_one(path: &PathBuf) -> Result<ChallengeConfig> {
     Ok(parsed)
 }
 
+/// Convert env in NAME=VALUE list form to a map
+fn env_to_map(env: ListOrMap) -> Result<ListOrMap> {
+    match env {
+        ListOrMap::Map(m) => Ok(ListOrMap::Map(m)),
+        ListOrMap::List(l) => {
+            // split NAME=VALUE list into separate name and value
+            let split: Vec<(String, String)> = l
+                .into_iter()
+                .map(|var| {
+                    // error if envvar is malformed
+                    let split = var.splitn(2, '=').collect_vec();
+                    if split.len() == 2 {
+                        Ok((split[0].to_string(), split[1].to_string()))
+                    } else {
+                        Err(anyhow!("Cannot split envvar {var:?}"))
+                    }
+                })
+                .collect::<Result<_>>()?;
+            // build hashmap from split name and value iteratively. this
+            // can't use HashMap::from() here since the values are dynamic
+            // and from() only works for Vec constants
+            let map = split
+                .into_iter()
+                .fold(Map::new(), |mut map, (name, value)| {
+                    map.insert(name, EnvValue::Value(value));
+                    map
+                });
+            Ok(ListOrMap::Map(map))
+        }
+    }
+}
+
 //
 // ==== Structs for challenge.yaml parsing ====
 //
@@ -139,27 +228,96 @@ pub struct ChallengeConfig {
 
     #[serde(default)]
     pods: Vec<Pod>, // optional if no containers used
+
+    /// Deploy separate copies of the challenge pods per team on demand,
+    /// instead of one shared deployment
+    #[serde(default)]
+    instanced: bool,
+
+    /// Override the ResourceQuota for the challenge namespace, instead of
+    /// the one calculated from the challenge pods. Challenges with extra
+    /// `manifests` only get a quota if this is set.
+    resource_quota: Option<Resource>,
+
+    /// Extra Kubernetes manifest templates to deploy alongside the challenge,
+    /// as glob patterns relative to the challenge directory (e.g. `k8s/*.yaml`)
+    #[serde(default)]
+    manifests: Vec<String>,
 }
 impl ChallengeConfig {
+    /// The literal flag, or `None` if it is checked by a regex or verifier
+    /// instead of being a fixed value.
+    pub fn static_flag(&self) -> Result<Option<String>> {
+        match &self.flag {
+            FlagType::RawString(flag) | FlagType::Text { text: flag } => Ok(Some(flag.clone())),
+            FlagType::File { file } => {
+                let path = self.directory.join(file);
+                fs::read_to_string(&path)
+                    .map(|f| Some(f.trim().to_string()))
+                    .with_context(|| format!("could not read flag file {path:?}"))
+            }
+            FlagType::Regex { .. } | FlagType::Verifier { .. } => Ok(None),
+        }
+    }
+
     /// Return the container image tag for the pod; either the upstream image or
     /// the tag to be built if the image is to be built from source.
     pub fn container_tag_for_pod(&self, profile_name: &str, pod_name: &str) -> Result<String> {
-        let config = get_config()?;
         let pod = self
             .pods
             .iter()
             .find(|p| p.name == pod_name)
             .ok_or(anyhow!("pod {} not found in challenge", pod_name))?;
 
-        match &pod.image_source {
+        self.container_tag(profile_name, &pod.name, &pod.image_source)
+    }
+
+    /// Return the container image tag for an additional or init container in
+    /// a pod. Built images are tagged as container `<pod>-<container>`.
+    pub fn container_tag_for_sidecar(
+        &self,
+        profile_name: &str,
+        pod_name: &str,
+        container_name: &str,
+    ) -> Result<String> {
+        let pod = self
+            .pods
+            .iter()
+            .find(|p| p.name == pod_name)
+            .ok_or(anyhow!("pod {} not found in challenge", pod_name))?;
+        let container = pod
+            .sidecars()
+            .find(|c| c.name == container_name)
+            .ok_or(anyhow!(
+                "container {} not found in pod {}",
+                container_name,
+                pod_name
+            ))?;
+
+        self.container_tag(
+            profile_name,
+            &format!("{}-{}", pod.name, container.name),
+            &container.image_source,
+        )
+    }
+
+    fn container_tag(
+        &self,
+        profile_name: &str,
+        container_name: &str,
+        image_source: &ImageSource,
+    ) -> Result<String> {
+        let config = get_config()?;
+
+        match image_source {
             ImageSource::Image(t) => Ok(t.to_string()),
             // render image tag template from config
             ImageSource::Build(b) => render_strict(
-                &get_config()?.registry.tag_format,
+                &config.registry.tag_format,
                 minijinja::context! {
                     domain => config.registry.domain,
                     challenge => self.slugify(),
-                    container => pod.name,
+                    container => container_name,
                     profile => profile_name
                 },
             )
@@ -172,6 +330,39 @@ impl ChallengeConfig {
         self.slugify_slash().replace("/", "-")
     }
 
+    /// Return paths of all extra manifest files matching the challenge's
+    /// `manifests` patterns, in sorted order.
+    pub fn extra_manifest_paths(&self) -> Result<Vec<PathBuf>> {
+        self.manifests
+            .iter()
+            .map(|pattern| {
+                let full_pattern = self.directory.join(pattern);
+                glob(&full_pattern.to_string_lossy())
+                    .with_context(|| format!("invalid manifest glob {pattern:?}"))?
+                    .map(|path| path.map_err(Error::from))
+                    .collect::<Result<Vec<_>>>()
+            })
+            .flatten_ok()
+            .collect::<Result<Vec<_>>>()
+            .map(|paths| paths.into_iter().sorted().dedup().collect())
+    }
+
+    /// Total CPU (in millicores) and memory (in bytes) requested by all
+    /// replicas of all pods in the challenge, with autoscaled pods at their
+    /// maximum. Pods without `resources` count as the config defaults.
+    pub fn total_resources(&self) -> Result<(i64, u64)> {
+        let defaults = &get_config()?.defaults.resources;
+
+        self.pods.iter().try_fold((0, 0), |(cpu, memory), pod| {
+            let (pod_cpu, pod_memory) = pod.resources_per_replica(defaults)?;
+            let replicas = pod.max_replicas().max(0);
+            Ok((
+                cpu + pod_cpu * replicas,
+                memory + pod_memory * replicas as u64,
+            ))
+        })
+    }
+
     /// Create challenge category/name slug from directory path, with category slash
     pub fn slugify_slash(&self) -> String {
         self.directory
@@ -199,6 +390,10 @@ enum FlagType {
 
 // Parse each distinct kind of Provide action as a separate enum variant
 // TODO: enforce relative/absolute paths for repo/container Provide's (`validator` crate?)
+//
+// All kinds can also set `content_type` and `content_disposition` to override
+// the upload headers of their files, see `ProvideConfig::upload_headers()`,
+// and `private` to only give players expiring signed links to their files.
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 #[serde(untagged, deny_unknown_fields)]
 #[fully_pub]
@@ -209,6 +404,10 @@ enum ProvideConfig {
     FromRepo {
         #[serde(rename = "include")]
         files: Vec<PathBuf>,
+        content_type: Option<String>,
+        content_disposition: Option<String>,
+        #[serde(default)]
+        private: bool,
     },
     /// Rename single file before uploading.
     /// Single file with as: field without from:
@@ -217,6 +416,10 @@ enum ProvideConfig {
         from: PathBuf,
         #[serde(rename = "as")]
         to: PathBuf,
+        content_type: Option<String>,
+        content_disposition: Option<String>,
+        #[serde(default)]
+        private: bool,
     },
     /// Upload multiple files in zip archive
     /// Multiple files with as: field without from:
@@ -225,6 +428,10 @@ enum ProvideConfig {
         files: Vec<PathBuf>,
         #[serde(rename = "as")]
         archive_name: PathBuf,
+        content_type: Option<String>,
+        content_disposition: Option<String>,
+        #[serde(default)]
+        private: bool,
     },
 
     /// Upload file(s) from container as-is.
@@ -234,6 +441,10 @@ enum ProvideConfig {
         container: String,
         #[serde(rename = "include")]
         files: Vec<PathBuf>,
+        content_type: Option<String>,
+        content_disposition: Option<String>,
+        #[serde(default)]
+        private: bool,
     },
     /// Rename single file from container before uploading.
     /// Single file with as: field
@@ -244,6 +455,10 @@ enum ProvideConfig {
         from: PathBuf,
         #[serde(rename = "as")]
         to: PathBuf,
+        content_type: Option<String>,
+        content_disposition: Option<String>,
+        #[serde(default)]
+        private: bool,
     },
     /// Upload multiple files from container in zip archive
     /// Multiple files with as: field
@@ -254,13 +469,69 @@ enum ProvideConfig {
         files: Vec<PathBuf>,
         #[serde(rename = "as")]
         archive_name: PathBuf,
+        content_type: Option<String>,
+        content_disposition: Option<String>,
+        #[serde(default)]
+        private: bool,
     },
 }
+impl ProvideConfig {
+    /// Content-Type and Content-Disposition overrides for the uploaded files.
+    pub fn upload_headers(&self) -> (Option<&str>, Option<&str>) {
+        match self {
+            ProvideConfig::FromRepo {
+                content_type,
+                content_disposition,
+                ..
+            }
+            | ProvideConfig::FromRepoRename {
+                content_type,
+                content_disposition,
+                ..
+            }
+            | ProvideConfig::FromRepoArchive {
+                content_type,
+                content_disposition,
+                ..
+            }
+            | ProvideConfig::FromContainer {
+                content_type,
+                content_disposition,
+                ..
+            }
+            | ProvideConfig::FromContainerRename {
+                content_type,
+                content_disposition,
+                ..
+            }
+            | ProvideConfig::FromContainerArchive {
+                content_type,
+                content_disposition,
+                ..
+            } => (content_type.as_deref(), content_disposition.as_deref()),
+        }
+    }
+
+    /// Whether the files should only be downloadable through signed urls.
+    pub fn is_private(&self) -> bool {
+        match self {
+            ProvideConfig::FromRepo { private, .. }
+            | ProvideConfig::FromRepoRename { private, .. }
+            | ProvideConfig::FromRepoArchive { private, .. }
+            | ProvideConfig::FromContainer { private, .. }
+            | ProvideConfig::FromContainerRename { private, .. }
+            | ProvideConfig::FromContainerArchive { private, .. } => *private,
+        }
+    }
+}
 impl FromStr for ProvideConfig {
     type Err = Void;
     fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
         Ok(ProvideConfig::FromRepo {
             files: vec![PathBuf::from(s)],
+            content_type: None,
+            content_disposition: None,
+            private: false,
         })
     }
 }
@@ -276,10 +547,147 @@ struct Pod {
     #[serde(default)]
     env: ListOrMap,
 
+    /// Override the image entrypoint. Only allowed for upstream `image`s,
+    /// built images should set it in their Dockerfile instead.
+    #[serde(default)]
+    command: Vec<String>,
+    /// Override the image arguments
+    #[serde(default)]
+    args: Vec<String>,
+    /// Override the image working directory
+    workdir: Option<String>,
+
     resources: Option<Resource>,
+    #[serde(default = "default_replicas")]
     replicas: i64,
+    /// Scale replicas with CPU usage instead of using a fixed `replicas` count
+    autoscale: Option<Autoscale>,
+    ports: Vec<PortConfig>,
+    #[serde(default)]
+    volumes: Vec<Volume>,
+
+    /// Additional containers to run alongside the main one, e.g. a database
+    #[serde(default)]
+    containers: Vec<Container>,
+    /// Containers to run to completion before the pod starts
+    #[serde(default)]
+    init_containers: Vec<Container>,
+
+    /// Node placement for this pod. Unset fields use the profile defaults for
+    /// the challenge category.
+    #[serde(flatten)]
+    scheduling: Scheduling,
+}
+impl Pod {
+    /// All additional and init containers in this pod.
+    pub fn sidecars(&self) -> impl Iterator<Item = &Container> {
+        self.containers.iter().chain(self.init_containers.iter())
+    }
+
+    /// All ports of the pod's main and additional containers.
+    pub fn all_ports(&self) -> impl Iterator<Item = &PortConfig> {
+        self.ports
+            .iter()
+            .chain(self.containers.iter().flat_map(|c| c.ports.iter()))
+    }
+
+    /// CPU (in millicores) and memory (in bytes) requested by a single
+    /// replica of this pod. Containers without `resources` count as
+    /// `defaults`.
+    ///
+    /// Init containers run before the others, so only the largest of them
+    /// counts if it needs more than all of the regular containers together.
+    pub fn resources_per_replica(&self, defaults: &Resource) -> Result<(i64, u64)> {
+        let usage = |r: &Resource| -> Result<(i64, u64)> { Ok((r.cpu * 1000, r.memory_bytes()?)) };
+
+        let (mut cpu, mut memory) = std::iter::once(self.resources.as_ref())
+            .chain(self.containers.iter().map(|c| c.resources.as_ref()))
+            .map(|r| usage(r.unwrap_or(defaults)))
+            .fold_ok((0, 0), |(cpu, memory), (c, m)| (cpu + c, memory + m))?;
+
+        for init in self.init_containers.iter() {
+            let (init_cpu, init_memory) = usage(init.resources.as_ref().unwrap_or(defaults))?;
+            cpu = cpu.max(init_cpu);
+            memory = memory.max(init_memory);
+        }
+
+        Ok((cpu, memory))
+    }
+
+    /// Most replicas this pod can have running at once.
+    pub fn max_replicas(&self) -> i64 {
+        match &self.autoscale {
+            Some(a) => a.max,
+            None => self.replicas,
+        }
+    }
+}
+fn default_replicas() -> i64 {
+    1
+}
+
+/// Additional container in a pod. Containers in the same pod share
+/// localhost, so they can talk to each other directly.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[fully_pub]
+struct Container {
+    name: String,
+
+    #[serde(flatten)]
+    image_source: ImageSource,
+
+    #[serde(default)]
+    env: ListOrMap,
+
+    resources: Option<Resource>,
+    #[serde(default)]
     ports: Vec<PortConfig>,
-    volume: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[fully_pub]
+struct Volume {
+    name: String,
+    /// Where to mount the volume in the container
+    path: String,
+    #[serde(flatten)]
+    kind: VolumeKind,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[fully_pub]
+enum VolumeKind {
+    /// PersistentVolumeClaim that keeps its data across pod restarts
+    Persistent {
+        size: String,
+        #[serde(default = "default_access_mode")]
+        access_mode: String,
+        storage_class: Option<String>,
+    },
+    /// Scratch space that is removed with the pod, optionally in memory
+    Ephemeral {
+        #[serde(default)]
+        tmpfs: bool,
+        size: Option<String>,
+    },
+}
+fn default_access_mode() -> String {
+    "ReadWriteOnce".to_string()
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[fully_pub]
+struct Autoscale {
+    min: i64,
+    max: i64,
+    /// Target average CPU usage across replicas, as a percentage of the
+    /// pod's requested CPU. Default: 80
+    #[serde(default = "default_cpu_percent")]
+    cpu_percent: i64,
+}
+fn default_cpu_percent() -> i64 {
+    80
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
@@ -320,7 +728,7 @@ fn default_dockerfile() -> String {
 #[fully_pub]
 enum ListOrMap {
     List(Vec<String>),
-    Map(Map<String, String>),
+    Map(Map<String, EnvValue>),
 }
 impl Default for ListOrMap {
     fn default() -> Self {
@@ -328,6 +736,33 @@ impl Default for ListOrMap {
     }
 }
 
+/// Value of a pod environment variable. Anything other than a plain or
+/// templated value is stored in a Kubernetes Secret instead of directly in
+/// the Deployment.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+#[fully_pub]
+enum EnvValue {
+    /// Plain value, used as-is
+    Value(String),
+    /// Value rendered as a template with the hostnames of the challenge's
+    /// other pods, e.g. `{ template: "http://{{ services.api }}:8080" }`
+    Template { template: String },
+    /// Value of `secret` in the profile's `secrets`
+    Secret { secret: String },
+    /// The challenge flag, with `flag: true`
+    Flag { flag: bool },
+    /// Value of `key` in a sops-encrypted file, relative to the challenge
+    /// directory
+    Encrypted { file: PathBuf, key: String },
+}
+impl EnvValue {
+    /// Does this value need to be stored in a Secret?
+    pub fn is_secret(&self) -> bool {
+        !matches!(self, EnvValue::Value(_) | EnvValue::Template { .. })
+    }
+}
+
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 #[fully_pub]
 struct PortConfig {
@@ -341,4 +776,7 @@ struct PortConfig {
 enum ExposeType {
     Tcp(i64),
     Http(String),
+    /// Only reachable by other pods in the challenge, at a hostname of the
+    /// pod name
+    Internal,
 }
@@ -1,9 +1,10 @@
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use fully_pub::fully_pub;
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap as Map;
 use std::fs;
+use std::path::PathBuf;
 use tracing::{debug, error, info, trace, warn};
 
 use figment::providers::{Env, Format, Yaml};
@@ -23,6 +24,16 @@ pub fn parse() -> Result<RcdsConfig> {
             .replace("challenges.", "challenges_")
             .replace("s3.access.", "s3.access_")
             .replace("s3.secret.", "s3.secret_")
+            .replace("s3.content.addressed", "s3.content_addressed")
+            .replace("s3.superseded.grace", "s3.superseded_grace")
+            .replace("s3.private.url.expiry", "s3.private_url_expiry")
+            .replace("s3.multipart.chunk.size", "s3.multipart_chunk_size")
+            .replace(".deploy.timeout", ".deploy_timeout")
+            .replace("instancer.max.per.team", "instancer.max_per_team")
+            .replace(".node.selector", ".node_selector")
+            .replace("local.base.url", "local.base_url")
+            .replace("template.dir", "template_dir")
+            .replace(".challenge.budget", ".challenge_budget")
             .into()
     });
     trace!(
@@ -40,9 +51,27 @@ pub fn parse() -> Result<RcdsConfig> {
 
     trace!("got config: {config:#?}");
 
+    validate(&config)?;
+
     Ok(config)
 }
 
+/// Check settings that have limits beyond what their type allows.
+fn validate(config: &RcdsConfig) -> Result<()> {
+    for (name, profile) in config.profiles.iter() {
+        if let AssetStoreConfig::S3(s3) = &profile.assets {
+            if s3.private_url_expiry > MAX_PRIVATE_URL_EXPIRY {
+                bail!(
+                    "profile {name:?}: s3.private_url_expiry can be at most {MAX_PRIVATE_URL_EXPIRY} (7 days), got {}",
+                    s3.private_url_expiry
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
 //
 // ==== Structs for rcds.yaml parsing ====
 //
@@ -56,6 +85,11 @@ struct RcdsConfig {
     deploy: Map<String, ProfileDeploy>,
     profiles: Map<String, ProfileConfig>,
     points: Vec<ChallengePoints>,
+
+    /// Directory with user overrides for the built-in challenge manifest
+    /// templates. Any template file in this directory with the same name as a
+    /// built-in template (e.g. `deployment.yaml.j2`) is used instead.
+    template_dir: Option<PathBuf>,
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
@@ -117,6 +151,60 @@ struct Resource {
     cpu: i64,
     memory: String,
 }
+impl Resource {
+    /// Parse `memory` from Kubernetes quantity notation (e.g. `500M`, `1Gi`)
+    /// into bytes.
+    pub fn memory_bytes(&self) -> Result<u64> {
+        parse_memory(&self.memory)
+    }
+}
+
+/// Parse a Kubernetes memory quantity (e.g. `500M`, `1.5Gi`, `1024`) into
+/// bytes. Fractional bytes are rounded up, like Kubernetes does.
+pub fn parse_memory(quantity: &str) -> Result<u64> {
+    let quantity = quantity.trim();
+    let split = quantity
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(quantity.len());
+    let (number, suffix) = quantity.split_at(split);
+
+    let multiplier: u64 = match suffix {
+        "" => 1,
+        "k" => 1000,
+        "M" => 1000u64.pow(2),
+        "G" => 1000u64.pow(3),
+        "T" => 1000u64.pow(4),
+        "Ki" => 1024,
+        "Mi" => 1024u64.pow(2),
+        "Gi" => 1024u64.pow(3),
+        "Ti" => 1024u64.pow(4),
+        _ => bail!("unknown memory unit {suffix:?} in {quantity:?}"),
+    };
+
+    // parse the whole and fractional parts separately to avoid float rounding
+    let invalid = || format!("invalid memory amount {quantity:?}");
+    let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+    if whole.is_empty() && fraction.is_empty() {
+        bail!(invalid());
+    }
+    let whole: u64 = match whole {
+        "" => 0,
+        w => w.parse().with_context(invalid)?,
+    };
+    let fraction_bytes = match fraction {
+        "" => 0,
+        f => {
+            let scale = 10u128.checked_pow(f.len() as u32).with_context(invalid)?;
+            let digits: u128 = f.parse().with_context(invalid)?;
+            (digits * multiplier as u128).div_ceil(scale) as u64
+        }
+    };
+
+    whole
+        .checked_mul(multiplier)
+        .and_then(|b| b.checked_add(fraction_bytes))
+        .with_context(invalid)
+}
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 #[fully_pub]
@@ -141,8 +229,119 @@ struct ProfileConfig {
     challenges_domain: String,
     kubeconfig: Option<String>,
     kubecontext: String,
-    s3: S3Config,
+    /// Where challenge assets are stored, one of `s3`, `local`, or `frontend`
+    #[serde(flatten)]
+    assets: AssetStoreConfig,
     dns: serde_yml::Value,
+
+    /// How long to wait for each challenge resource to become ready during
+    /// deploy before giving up, in seconds. Default: 300 (5 minutes)
+    #[serde(default = "default_deploy_timeout")]
+    deploy_timeout: u64,
+
+    /// Settings for per-team instances of `instanced` challenges
+    #[serde(default)]
+    instancer: InstancerConfig,
+
+    /// Maximum total resources a single challenge can request across all of
+    /// its pod replicas. Checked by `validate`.
+    challenge_budget: Option<Resource>,
+
+    /// Default pod scheduling settings for each challenge category, e.g. to
+    /// run all `pwn` challenges on a dedicated node pool. Pods can override
+    /// these individually.
+    #[serde(default)]
+    scheduling: Map<String, Scheduling>,
+
+    /// Secret values that challenge pods can use in their env with
+    /// `{ secret: NAME }`. When set through environment variables, names are
+    /// lowercased and cannot contain underscores.
+    #[serde(default)]
+    secrets: Map<String, String>,
+}
+fn default_deploy_timeout() -> u64 {
+    5 * 60
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[fully_pub]
+struct InstancerConfig {
+    /// How long an instance lives before it is garbage collected, in seconds.
+    /// Default: 1800 (30 minutes)
+    #[serde(default = "default_instance_ttl")]
+    ttl: u64,
+
+    /// How many instances of a single challenge each team can have running at
+    /// once. Default: 1
+    #[serde(default = "default_instances_per_team")]
+    max_per_team: usize,
+}
+impl Default for InstancerConfig {
+    fn default() -> Self {
+        InstancerConfig {
+            ttl: default_instance_ttl(),
+            max_per_team: default_instances_per_team(),
+        }
+    }
+}
+fn default_instance_ttl() -> u64 {
+    30 * 60
+}
+fn default_instances_per_team() -> usize {
+    1
+}
+
+/// Where challenge pods are placed in the cluster.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Default)]
+#[fully_pub]
+struct Scheduling {
+    /// Only run on nodes with all of these labels
+    node_selector: Option<Map<String, String>>,
+    /// Allow running on nodes with matching taints
+    tolerations: Option<Vec<Toleration>>,
+    /// Prefer running replicas on different nodes
+    spread: Option<bool>,
+}
+impl Scheduling {
+    /// Fill in any unset settings from `defaults`.
+    pub fn or(&self, defaults: Option<&Scheduling>) -> Scheduling {
+        let defaults = match defaults {
+            Some(d) => d,
+            None => return self.clone(),
+        };
+
+        Scheduling {
+            node_selector: self
+                .node_selector
+                .clone()
+                .or_else(|| defaults.node_selector.clone()),
+            tolerations: self
+                .tolerations
+                .clone()
+                .or_else(|| defaults.tolerations.clone()),
+            spread: self.spread.or(defaults.spread),
+        }
+    }
+}
+
+/// Kubernetes pod toleration, see
+/// <https://kubernetes.io/docs/concepts/scheduling-eviction/taint-and-toleration/>
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+#[fully_pub]
+struct Toleration {
+    /// Taint key to match. Left out with the `Exists` operator to tolerate
+    /// every taint.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    key: Option<String>,
+    #[serde(default = "default_toleration_operator")]
+    operator: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    value: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    effect: Option<String>,
+}
+fn default_toleration_operator() -> String {
+    "Equal".to_string()
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
@@ -153,6 +352,19 @@ struct ChallengePoints {
     max: i64,
 }
 
+/// Storage for challenge assets that players download.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[fully_pub]
+enum AssetStoreConfig {
+    /// S3-compatible bucket
+    S3(S3Config),
+    /// Directory served by some other web server, e.g. nginx
+    Local(LocalAssetConfig),
+    /// rCTF's own upload API, using the profile's frontend url and token
+    Frontend(FrontendAssetConfig),
+}
+
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 #[fully_pub]
 struct S3Config {
@@ -161,4 +373,48 @@ struct S3Config {
     region: String,
     access_key: String,
     secret_key: String,
+
+    /// Include a short hash of each asset's contents in its path, so updated
+    /// files get a new URL instead of caches serving the old version.
+    #[serde(default)]
+    content_addressed: bool,
+    /// How long to keep the previous version of a content-addressed asset
+    /// after a new version is uploaded, in seconds. Old versions are removed
+    /// by the first deploy after this. Default: 3600 (1 hour)
+    #[serde(default = "default_superseded_grace")]
+    superseded_grace: u64,
+    /// How long signed urls for `private` assets stay valid, in seconds. Run
+    /// `refresh-urls` more often than this to keep them working. At most 7
+    /// days. Default: 86400 (1 day)
+    #[serde(default = "default_private_url_expiry")]
+    private_url_expiry: u32,
+    /// Size of each part when uploading large assets, in MiB. Files larger
+    /// than this are uploaded in parts, and an interrupted upload resumes
+    /// from the last finished part. At least 5. Default: 64
+    #[serde(default = "default_multipart_chunk_size")]
+    multipart_chunk_size: u64,
+}
+fn default_superseded_grace() -> u64 {
+    60 * 60
+}
+/// S3 refuses to sign urls that are valid for longer than 7 days
+const MAX_PRIVATE_URL_EXPIRY: u32 = 7 * 24 * 60 * 60;
+fn default_private_url_expiry() -> u32 {
+    24 * 60 * 60
+}
+fn default_multipart_chunk_size() -> u64 {
+    64
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[fully_pub]
+struct LocalAssetConfig {
+    /// Directory to copy assets into
+    path: PathBuf,
+    /// URL that `path` is served at
+    base_url: String,
 }
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[fully_pub]
+struct FrontendAssetConfig {}
@@ -0,0 +1,166 @@
+// Assets uploaded through rCTF's admin upload API. rCTF stores uploads by
+// their hash and handles serving them itself, but it has no API to list or
+// remove uploads.
+
+use std::fs;
+
+use anyhow::{anyhow, bail, Context, Result};
+use base64::prelude::{Engine, BASE64_STANDARD};
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, trace};
+
+use crate::builder::Asset;
+use crate::configparser::config::ProfileConfig;
+use crate::configparser::ChallengeConfig;
+use crate::deploy::frontend::FrontendApi;
+
+use super::{asset_headers, asset_path, AssetStore, UploadOutcome};
+
+/// Assets uploaded to the rCTF frontend.
+pub struct FrontendStore<'a> {
+    pub profile: &'a ProfileConfig,
+    api: FrontendApi,
+}
+
+/// An upload as returned from rCTF.
+#[derive(Debug, Deserialize)]
+struct Upload {
+    name: String,
+    url: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct UploadQuery<'a> {
+    name: &'a str,
+    sha256: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+struct UploadFile<'a> {
+    name: &'a str,
+    /// File contents as a `data:` uri
+    data: String,
+}
+
+impl<'a> FrontendStore<'a> {
+    pub fn new(profile: &'a ProfileConfig) -> Result<Self> {
+        Ok(FrontendStore {
+            profile,
+            api: FrontendApi::new(profile)?,
+        })
+    }
+
+    /// Call upload API `endpoint` with `body`, returning its response data.
+    async fn call<T: for<'de> Deserialize<'de>>(
+        &self,
+        endpoint: &str,
+        body: serde_json::Value,
+    ) -> Result<T> {
+        self.api
+            .call(Method::POST, endpoint, Some(body))
+            .await?
+            .ok_or_else(|| anyhow!("frontend returned no data for {endpoint}"))
+    }
+
+    /// Upload `contents` as `name`, returning the url it is served at.
+    async fn upload_file(&self, name: &str, content_type: &str, contents: &[u8]) -> Result<String> {
+        let files = [UploadFile {
+            name,
+            data: format!(
+                "data:{content_type};base64,{}",
+                BASE64_STANDARD.encode(contents)
+            ),
+        }];
+        let uploads: Vec<Upload> = self
+            .call("upload", serde_json::json!({ "files": files }))
+            .await?;
+
+        uploads
+            .into_iter()
+            .find(|u| u.name == name)
+            .and_then(|u| u.url)
+            .ok_or_else(|| anyhow!("frontend did not return url for uploaded file {name:?}"))
+    }
+}
+
+impl AssetStore for FrontendStore<'_> {
+    async fn upload(
+        &self,
+        chal: &ChallengeConfig,
+        asset: &Asset,
+    ) -> Result<(Asset, UploadOutcome)> {
+        let file = &asset.path;
+        if asset.private {
+            bail!("frontend asset store cannot sign urls for private asset {file:?}");
+        }
+        let checksum = &asset.sha256;
+        let name = file.file_name().unwrap().to_string_lossy();
+        let (content_type, _) = asset_headers(asset);
+
+        let uploaded = Asset {
+            path: asset_path(chal, asset, None),
+            ..asset.clone()
+        };
+
+        // rCTF already has the file if it knows its hash
+        let query = [UploadQuery {
+            name: &name,
+            sha256: checksum,
+        }];
+        let existing: Vec<Upload> = self
+            .call("upload/query", serde_json::json!({ "uploads": query }))
+            .await?;
+        if let Some(url) = existing.into_iter().find_map(|u| u.url) {
+            trace!("{:?} is already uploaded at {url:?}, skipping", file);
+            let uploaded = Asset {
+                url: Some(url),
+                ..uploaded
+            };
+            return Ok((uploaded, UploadOutcome::Skipped));
+        }
+
+        trace!("uploading {:?} to frontend", file);
+        let contents = fs::read(file).with_context(|| format!("could not read {file:?}"))?;
+        let url = self.upload_file(&name, &content_type, &contents).await?;
+
+        let uploaded = Asset {
+            url: Some(url),
+            ..uploaded
+        };
+        Ok((uploaded, UploadOutcome::Uploaded(contents.len() as u64)))
+    }
+
+    async fn list(&self) -> Result<Vec<String>> {
+        debug!("frontend does not support listing uploads, not checking for old assets");
+        Ok(vec![])
+    }
+
+    async fn delete(&self, path: &str) -> Result<()> {
+        bail!("frontend does not support removing uploads, cannot delete {path:?}")
+    }
+
+    async fn check(&self) -> Result<()> {
+        // rCTF does not care if the same file is uploaded more than once
+        debug!("uploading test file to frontend");
+        let test_file = ("beavercds-test-file", "access test file!");
+        let url = self
+            .upload_file(test_file.0, "text/plain", test_file.1.as_bytes())
+            .await
+            .context("could not upload to frontend")?;
+
+        debug!("downloading test file from {url}");
+        let from_public = reqwest::get(&url)
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| anyhow!(e))
+            .with_context(|| format!("public download of {url:?} failed"))?
+            .text()
+            .await?;
+        if from_public != test_file.1 {
+            bail!("contents of uploaded test file do not match");
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,125 @@
+use std::fs;
+
+use anyhow::{anyhow, bail, Context, Result};
+use glob::glob;
+use itertools::Itertools;
+use tracing::{debug, trace, warn};
+
+use crate::builder::Asset;
+use crate::configparser::config::LocalAssetConfig;
+use crate::configparser::ChallengeConfig;
+use crate::utils::file_sha256;
+
+use super::{asset_path, AssetStore, UploadOutcome};
+
+/// Assets copied to a local directory, which is served by some other web
+/// server.
+pub struct LocalStore<'a> {
+    pub config: &'a LocalAssetConfig,
+}
+
+impl<'a> LocalStore<'a> {
+    pub fn new(config: &'a LocalAssetConfig) -> Self {
+        LocalStore { config }
+    }
+
+    /// Public url for stored `path`.
+    fn url(&self, path: &str) -> String {
+        format!("{}/{path}", self.config.base_url.trim_end_matches('/'))
+    }
+}
+
+impl AssetStore for LocalStore<'_> {
+    async fn upload(
+        &self,
+        chal: &ChallengeConfig,
+        asset: &Asset,
+    ) -> Result<(Asset, UploadOutcome)> {
+        let file = &asset.path;
+        if asset.private {
+            bail!("local asset store cannot sign urls for private asset {file:?}");
+        }
+        let stored_path = asset_path(chal, asset, None);
+        let dest = self.config.path.join(&stored_path);
+
+        // headers are up to whatever is serving the directory
+        if asset.content_type.is_some() || asset.content_disposition.is_some() {
+            warn!("local asset store cannot set headers, ignoring overrides for {file:?}");
+        }
+
+        let uploaded = Asset {
+            url: Some(self.url(&stored_path.to_string_lossy())),
+            path: stored_path,
+            ..asset.clone()
+        };
+
+        if dest.exists() && file_sha256(&dest)? == asset.sha256 {
+            trace!("{:?} is unchanged at {:?}, skipping", file, dest);
+            return Ok((uploaded, UploadOutcome::Skipped));
+        }
+
+        trace!("copying {:?} to {:?}", file, dest);
+        fs::create_dir_all(dest.parent().unwrap())
+            .with_context(|| format!("could not create asset directory for {dest:?}"))?;
+        let bytes = fs::copy(file, &dest)
+            .with_context(|| format!("could not copy {file:?} to {dest:?}"))?;
+
+        Ok((uploaded, UploadOutcome::Uploaded(bytes)))
+    }
+
+    async fn list(&self) -> Result<Vec<String>> {
+        let pattern = self.config.path.join("assets/**/*");
+        let paths = glob(&pattern.to_string_lossy())?
+            .filter_ok(|p| p.is_file())
+            .map_ok(|p| {
+                p.strip_prefix(&self.config.path)
+                    .unwrap()
+                    .to_string_lossy()
+                    .to_string()
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .context("could not list asset directory")?;
+
+        trace!("found stored assets: {paths:?}");
+        Ok(paths)
+    }
+
+    async fn delete(&self, path: &str) -> Result<()> {
+        let full_path = self.config.path.join(path);
+        fs::remove_file(&full_path).with_context(|| format!("could not delete {full_path:?}"))
+    }
+
+    async fn check(&self) -> Result<()> {
+        // try writing file to the directory
+        debug!("writing test file to asset directory");
+        let test_file = ("beavercds-test-file", "access test file!");
+        let test_path = self.config.path.join(test_file.0);
+        fs::write(&test_path, test_file.1).with_context(|| {
+            format!("could not write to asset directory {:?}", self.config.path)
+        })?;
+
+        // download it through the web server to check public access
+        debug!("downloading test file from {}", self.url(test_file.0));
+        let from_public = reqwest::get(self.url(test_file.0))
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| anyhow!(e))
+            .with_context(|| {
+                format!(
+                    "public download from asset url {:?} failed",
+                    self.config.base_url
+                )
+            })?
+            .text()
+            .await?;
+
+        // clean up test file before checking, so it is not left behind
+        fs::remove_file(&test_path)?;
+
+        if from_public != test_file.1 {
+            bail!("contents of public asset url do not match written file");
+        }
+
+        Ok(())
+    }
+}
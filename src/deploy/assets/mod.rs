@@ -0,0 +1,412 @@
+// Storage for challenge files that players download. Assets can go to an S3
+// bucket, a local directory served by some other web server, or to the
+// frontend's own upload API. Each of these implements `AssetStore`.
+
+pub mod frontend;
+pub mod local;
+mod multipart;
+pub mod s3;
+
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use itertools::Itertools;
+use tracing::{debug, info, trace};
+
+use crate::builder::{Asset, BuildResult};
+use crate::configparser::config::{AssetStoreConfig, ProfileConfig};
+use crate::configparser::{enabled_challenges, get_profile_config, ChallengeConfig};
+use crate::utils::{file_sha256, TryJoinAll};
+
+use frontend::FrontendStore;
+use local::LocalStore;
+use s3::S3Store;
+
+/// Name of the checksum listing uploaded with each challenge's assets
+pub const CHECKSUMS_FILENAME: &str = "SHA256SUMS";
+
+/// What happened to a single asset file during upload.
+#[derive(Debug)]
+pub enum UploadOutcome {
+    /// File was uploaded, with this many bytes
+    Uploaded(u64),
+    /// File is unchanged from what is already stored
+    Skipped,
+}
+
+/// A place to store challenge assets for players to download.
+#[allow(async_fn_in_trait)]
+pub trait AssetStore {
+    /// Store `asset` for `chal`, unless the same file is already stored.
+    /// Returns the asset with its path set to where it was stored and its
+    /// download url filled in.
+    async fn upload(&self, chal: &ChallengeConfig, asset: &Asset)
+        -> Result<(Asset, UploadOutcome)>;
+
+    /// Clean up after all of `chal`'s current assets were uploaded.
+    async fn finish_challenge(&self, _chal: &ChallengeConfig, _uploaded: &[Asset]) -> Result<()> {
+        Ok(())
+    }
+
+    /// Paths of all stored assets.
+    async fn list(&self) -> Result<Vec<String>>;
+
+    /// Remove the stored asset at `path`, as returned by `list()`.
+    async fn delete(&self, path: &str) -> Result<()>;
+
+    /// Check that assets can be stored and then downloaded by players.
+    async fn check(&self) -> Result<()>;
+}
+
+/// The asset store configured for a profile.
+pub enum Store<'a> {
+    S3(S3Store<'a>),
+    Local(LocalStore<'a>),
+    Frontend(FrontendStore<'a>),
+}
+
+/// Get the asset store configured for `profile`.
+pub fn asset_store(profile: &ProfileConfig) -> Result<Store<'_>> {
+    Ok(match &profile.assets {
+        AssetStoreConfig::S3(config) => Store::S3(S3Store::new(config)?),
+        AssetStoreConfig::Local(config) => Store::Local(LocalStore::new(config)),
+        AssetStoreConfig::Frontend(_) => Store::Frontend(FrontendStore::new(profile)?),
+    })
+}
+
+impl Store<'_> {
+    /// Where assets are stored, for logs.
+    pub fn describe(&self) -> String {
+        match self {
+            Store::S3(s) => format!("bucket {:?}", s.config.bucket_name),
+            Store::Local(s) => format!("directory {:?}", s.config.path),
+            Store::Frontend(s) => format!("frontend {:?}", s.profile.frontend_url),
+        }
+    }
+}
+
+/// Call the same method on whichever kind of store this is.
+macro_rules! delegate {
+    ($self:ident.$method:ident($($arg:expr),*)) => {
+        match $self {
+            Store::S3(s) => s.$method($($arg),*).await,
+            Store::Local(s) => s.$method($($arg),*).await,
+            Store::Frontend(s) => s.$method($($arg),*).await,
+        }
+    };
+}
+
+impl AssetStore for Store<'_> {
+    async fn upload(
+        &self,
+        chal: &ChallengeConfig,
+        asset: &Asset,
+    ) -> Result<(Asset, UploadOutcome)> {
+        delegate!(self.upload(chal, asset))
+    }
+
+    async fn finish_challenge(&self, chal: &ChallengeConfig, uploaded: &[Asset]) -> Result<()> {
+        delegate!(self.finish_challenge(chal, uploaded))
+    }
+
+    async fn list(&self) -> Result<Vec<String>> {
+        delegate!(self.list())
+    }
+
+    async fn delete(&self, path: &str) -> Result<()> {
+        delegate!(self.delete(path))
+    }
+
+    async fn check(&self) -> Result<()> {
+        delegate!(self.check())
+    }
+}
+
+/// Upload asset files to the profile's asset store. Files that are unchanged
+/// from what is already stored are skipped.
+/// Returns the build results with assets set to their stored paths and urls.
+pub async fn upload_assets(
+    profile_name: &str,
+    build_results: &[(&ChallengeConfig, BuildResult)],
+) -> Result<Vec<BuildResult>> {
+    let profile = get_profile_config(profile_name)?;
+    let store = &asset_store(profile)?;
+
+    info!("uploading assets to {}...", store.describe());
+
+    // upload all files for each challenge
+    let results = build_results
+        .iter()
+        .map(|(chal, result)| async move {
+            // upload all files for a specific challenge
+
+            info!("  for chal {:?}...", chal.directory);
+
+            // list checksums next to the files, for players to verify downloads
+            let sums_dir = tempfile::tempdir()?;
+            let sums = checksum_files(&result.assets, sums_dir.path())?;
+
+            let uploaded = result
+                .assets
+                .iter()
+                .chain(sums.iter())
+                .map(|asset| async move {
+                    store
+                        .upload(chal, asset)
+                        .await
+                        .with_context(|| format!("failed to upload file {:?}", asset.path))
+                })
+                .try_join_all()
+                .await
+                .with_context(|| {
+                    format!("failed to upload asset files for chal {:?}", chal.directory)
+                })?;
+
+            let current = uploaded.iter().map(|(a, _)| a.clone()).collect_vec();
+            store
+                .finish_challenge(chal, &current)
+                .await
+                .with_context(|| {
+                    format!(
+                        "failed to clean up old assets for chal {:?}",
+                        chal.directory
+                    )
+                })?;
+
+            anyhow::Ok(uploaded)
+        })
+        .try_join_all()
+        .await?;
+
+    let outcomes = results.iter().flatten().map(|(_, o)| o).collect_vec();
+    let uploaded_bytes: u64 = outcomes
+        .iter()
+        .map(|o| match o {
+            UploadOutcome::Uploaded(bytes) => *bytes,
+            UploadOutcome::Skipped => 0,
+        })
+        .sum();
+    let skipped = outcomes
+        .iter()
+        .filter(|o| matches!(o, UploadOutcome::Skipped))
+        .count();
+    info!(
+        "uploaded {} files ({uploaded_bytes} bytes), skipped {skipped} unchanged files",
+        outcomes.len() - skipped,
+    );
+
+    // return new BuildResults with assets as stored
+    Ok(build_results
+        .iter()
+        .zip(results)
+        .map(|((_, result), uploaded)| BuildResult {
+            tags: result.tags.clone(),
+            assets: uploaded.into_iter().map(|(asset, _)| asset).collect(),
+        })
+        .collect())
+}
+
+/// Remove stored assets that are no longer provided: files removed from or
+/// renamed in a challenge's `provide`, and everything for challenges that are
+/// not enabled for the profile. Returns the removed paths.
+///
+/// With `dry_run`, nothing is deleted and the paths that would be removed are
+/// returned instead.
+pub async fn remove_stale_assets(
+    profile_name: &str,
+    build_results: &[(&ChallengeConfig, BuildResult)],
+    dry_run: bool,
+) -> Result<Vec<String>> {
+    let profile = get_profile_config(profile_name)?;
+    let store = asset_store(profile)?;
+
+    // filenames each enabled challenge currently provides, public and private
+    // separately. old content-addressed versions of these are left to the
+    // store and its grace period
+    let current = build_results
+        .iter()
+        .flat_map(|(chal, result)| {
+            [false, true].map(|private| {
+                let mut filenames = result
+                    .assets
+                    .iter()
+                    .filter(|a| a.private == private)
+                    .filter_map(|a| a.path.file_name())
+                    .collect_vec();
+                if !filenames.is_empty() {
+                    filenames.push(OsStr::new(CHECKSUMS_FILENAME));
+                }
+                (asset_prefix(chal, private), filenames)
+            })
+        })
+        .collect_vec();
+
+    let stale = store
+        .list()
+        .await
+        .context("could not list stored assets")?
+        .into_iter()
+        .filter(|path| {
+            match current.iter().find(|(prefix, _)| path.starts_with(prefix)) {
+                Some((_, filenames)) => Path::new(path)
+                    .file_name()
+                    .is_none_or(|name| !filenames.contains(&name)),
+                // challenge is not enabled anymore
+                None => true,
+            }
+        })
+        .collect_vec();
+
+    for path in stale.iter() {
+        if dry_run {
+            info!("  would remove stale asset {path:?}");
+        } else {
+            info!("  removing stale asset {path:?}");
+            store
+                .delete(path)
+                .await
+                .with_context(|| format!("could not delete stale asset {path:?}"))?;
+        }
+    }
+
+    Ok(stale)
+}
+
+/// Stored assets of all enabled challenges with fresh download urls, without
+/// rebuilding or uploading anything. Used to re-sign private asset urls before
+/// they expire.
+pub async fn refresh_urls(profile_name: &str) -> Result<Vec<(&ChallengeConfig, Vec<Asset>)>> {
+    let profile = get_profile_config(profile_name)?;
+    let Store::S3(store) = &asset_store(profile)? else {
+        bail!("only s3 asset stores have signed urls to refresh");
+    };
+
+    enabled_challenges(profile_name)?
+        .into_iter()
+        .map(|chal| async move {
+            let assets = store
+                .stored_assets(chal)
+                .await
+                .with_context(|| format!("could not list assets for chal {:?}", chal.directory))?;
+            debug!(
+                "refreshed {} asset urls for chal {:?}",
+                assets.len(),
+                chal.directory
+            );
+            anyhow::Ok((chal, assets))
+        })
+        .try_join_all()
+        .await
+}
+
+/// Write a `SHA256SUMS` file into `dir` for the public and for the private
+/// files in `assets`, if there are any of each. Private files are listed
+/// separately so their names are not public.
+pub(crate) fn checksum_files(assets: &[Asset], dir: &Path) -> Result<Vec<Asset>> {
+    [false, true]
+        .into_iter()
+        .filter_map(|private| {
+            let lines = assets
+                .iter()
+                .filter(|a| a.private == private)
+                .map(|a| {
+                    format!(
+                        "{}  {}\n",
+                        a.sha256,
+                        a.path.file_name().unwrap().to_string_lossy()
+                    )
+                })
+                .sorted()
+                .collect::<String>();
+            (!lines.is_empty()).then_some((private, lines))
+        })
+        .map(|(private, lines)| {
+            let path = dir
+                .join(if private { "private" } else { "public" })
+                .join(CHECKSUMS_FILENAME);
+            fs::create_dir_all(path.parent().unwrap())?;
+            fs::write(&path, &lines)?;
+            Ok(Asset {
+                sha256: file_sha256(&path)?,
+                path,
+                content_type: None,
+                content_disposition: None,
+                private,
+                url: None,
+            })
+        })
+        .collect()
+}
+
+/// Path that all public or private assets for `chal` are stored under.
+fn asset_prefix(chal: &ChallengeConfig, private: bool) -> String {
+    let root = if private { "private" } else { "assets" };
+    format!("{root}/{}/", chal.directory.to_string_lossy())
+}
+
+/// Stored path for `asset` of `chal`, e.g. `assets/misc/foo/stuff.zip`, or
+/// under `private/` for private assets. If `content_hash` is given, it is
+/// included in the path, e.g. `assets/misc/foo/3fa9c1d2/stuff.zip`.
+fn asset_path(chal: &ChallengeConfig, asset: &Asset, content_hash: Option<&str>) -> PathBuf {
+    let file = &asset.path;
+    let filename = file.file_name().unwrap().to_string_lossy();
+    let prefix = asset_prefix(chal, asset.private);
+    let path = match content_hash {
+        Some(hash) => format!("{prefix}{hash}/{filename}"),
+        None => format!("{prefix}{filename}"),
+    };
+    trace!("storing {file:?} at {path:?}");
+    PathBuf::from(path)
+}
+
+/// Content-Type and Content-Disposition to serve `asset` with. Unless
+/// overridden, browsers should download handouts instead of trying to
+/// display them.
+fn asset_headers(asset: &Asset) -> (String, String) {
+    let content_type = match &asset.content_type {
+        Some(t) => t.clone(),
+        None => mime_guess::from_path(&asset.path)
+            .first_or_octet_stream()
+            .to_string(),
+    };
+    let content_disposition = match &asset.content_disposition {
+        Some(d) => d.clone(),
+        None => attachment_disposition(&asset.path.file_name().unwrap().to_string_lossy()),
+    };
+    debug!("serving {:?} as {content_type}", asset.path);
+
+    (content_type, content_disposition)
+}
+
+/// `attachment` Content-Disposition for downloading a file as `filename`.
+/// The quoted `filename` only allows ASCII, so names with other characters
+/// also get an RFC 6266 `filename*` with the full UTF-8 name that browsers
+/// use instead.
+pub(crate) fn attachment_disposition(filename: &str) -> String {
+    let fallback: String = filename
+        .chars()
+        .map(|c| match c {
+            '"' | '\\' => format!("\\{c}"),
+            c if c.is_ascii() && !c.is_ascii_control() => c.to_string(),
+            _ => "_".to_string(),
+        })
+        .collect();
+
+    if filename.is_ascii() {
+        return format!("attachment; filename=\"{fallback}\"");
+    }
+
+    // percent-encode everything but RFC 5987 `attr-char`s
+    let encoded: String = filename
+        .bytes()
+        .map(|b| match b {
+            b if b.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&b) => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect();
+    format!("attachment; filename=\"{fallback}\"; filename*=UTF-8''{encoded}")
+}
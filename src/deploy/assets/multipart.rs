@@ -0,0 +1,228 @@
+// Multipart uploads for large assets. Parts are uploaded one at a time and
+// recorded in a local state file as they finish, so an interrupted upload can
+// pick up from the last completed part on the next deploy instead of starting
+// over.
+
+use std::env;
+use std::fs;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use s3::serde_types::Part;
+use s3::Bucket;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tracing::{debug, info, trace, warn};
+
+/// Where in-progress upload state is kept, relative to the user's cache
+/// directory
+const STATE_DIR: &str = "beavercds/uploads";
+
+/// How often to log upload progress
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// S3 allows at most this many parts per upload
+const MAX_PARTS: u64 = 10_000;
+
+/// An upload that has been started but not completed yet.
+#[derive(Debug, Serialize, Deserialize)]
+struct UploadState {
+    key: String,
+    upload_id: String,
+    /// Checksum of the file being uploaded, so a changed file is not resumed
+    checksum: String,
+    chunk_size: u64,
+    /// Part numbers and etags of completed parts, in order
+    parts: Vec<(u32, String)>,
+}
+
+/// Directory for upload state files, under `$XDG_CACHE_HOME` or `~/.cache`
+/// so it stays out of the challenge repo.
+fn state_dir() -> PathBuf {
+    let cache = env::var_os("XDG_CACHE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+        // nowhere better to put it
+        .unwrap_or_else(env::temp_dir);
+    cache.join(STATE_DIR)
+}
+
+impl UploadState {
+    /// State file path for the upload to `key`.
+    fn path(key: &str) -> PathBuf {
+        state_dir().join(format!("{}.json", key.replace('/', "_")))
+    }
+
+    fn load(key: &str) -> Option<Self> {
+        let contents = fs::read_to_string(Self::path(key)).ok()?;
+        serde_json::from_str(&contents)
+            .inspect_err(|e| warn!("ignoring bad upload state for {key:?}: {e}"))
+            .ok()
+    }
+
+    fn save(&self) -> Result<()> {
+        fs::create_dir_all(state_dir()).context("could not create upload state directory")?;
+        fs::write(Self::path(&self.key), serde_json::to_string(self)?)
+            .with_context(|| format!("could not save upload state for {:?}", self.key))
+    }
+
+    fn remove(&self) {
+        if let Err(e) = fs::remove_file(Self::path(&self.key)) {
+            warn!("could not remove upload state for {:?}: {e}", self.key);
+        }
+    }
+}
+
+/// Upload `file` to `key` in chunks of at least `chunk_size` bytes, resuming
+/// a previous interrupted upload of the same file if there is one. `headers`
+/// is used to start the upload, so any headers or metadata set on it are
+/// applied to the finished object. Returns the number of bytes uploaded.
+pub async fn upload_multipart(
+    bucket: &Bucket,
+    headers: &Bucket,
+    file: &Path,
+    key: &str,
+    checksum: &str,
+    content_type: &str,
+    chunk_size: u64,
+) -> Result<u64> {
+    let size = fs::metadata(file)?.len();
+    // larger files need bigger parts to stay under the part limit
+    let chunk_size = chunk_size.max(size.div_ceil(MAX_PARTS));
+
+    let mut state = match resumable(bucket, key, checksum, chunk_size).await? {
+        Some(state) => {
+            info!(
+                "  resuming upload of {file:?} from part {}",
+                state.parts.len() + 1
+            );
+            state
+        }
+        None => {
+            let upload = headers
+                .initiate_multipart_upload(key, content_type)
+                .await
+                .with_context(|| format!("could not start upload of {key:?}"))?;
+            UploadState {
+                key: key.to_string(),
+                upload_id: upload.upload_id,
+                checksum: checksum.to_string(),
+                chunk_size,
+                parts: vec![],
+            }
+        }
+    };
+    state.save()?;
+
+    let mut asset_file = tokio::fs::File::open(file).await?;
+    let mut done = state.parts.len() as u64 * chunk_size;
+    asset_file.seek(SeekFrom::Start(done)).await?;
+
+    let started = Instant::now();
+    let mut last_report = started;
+    let mut sent = 0;
+
+    while done < size {
+        let mut chunk = Vec::with_capacity(chunk_size as usize);
+        (&mut asset_file)
+            .take(chunk_size)
+            .read_to_end(&mut chunk)
+            .await?;
+
+        let part_number = state.parts.len() as u32 + 1;
+        trace!("uploading part {part_number} of {key:?}");
+        let len = chunk.len() as u64;
+        let part = bucket
+            .put_multipart_chunk(chunk, key, part_number, &state.upload_id, content_type)
+            .await
+            .with_context(|| format!("could not upload part {part_number} of {key:?}"))?;
+
+        state.parts.push((part.part_number, part.etag));
+        state.save()?;
+        done += len;
+        sent += len;
+
+        if last_report.elapsed() >= PROGRESS_INTERVAL {
+            last_report = Instant::now();
+            info!(
+                "  {file:?}: {} / {} MiB ({})",
+                mib(done),
+                mib(size),
+                rate(sent, started.elapsed()),
+            );
+        }
+    }
+
+    let parts = state
+        .parts
+        .iter()
+        .map(|(part_number, etag)| Part {
+            part_number: *part_number,
+            etag: etag.clone(),
+        })
+        .collect();
+    bucket
+        .complete_multipart_upload(key, &state.upload_id, parts)
+        .await
+        .with_context(|| format!("could not finish upload of {key:?}"))?;
+    state.remove();
+
+    info!(
+        "  uploaded {file:?} in {} parts ({})",
+        state.parts.len(),
+        rate(sent, started.elapsed())
+    );
+
+    Ok(sent)
+}
+
+/// Previous upload of the same file to `key` that can be continued, if it is
+/// still in progress on the bucket. Uploads that cannot be resumed are
+/// aborted.
+async fn resumable(
+    bucket: &Bucket,
+    key: &str,
+    checksum: &str,
+    chunk_size: u64,
+) -> Result<Option<UploadState>> {
+    let Some(state) = UploadState::load(key) else {
+        return Ok(None);
+    };
+
+    let in_progress = bucket
+        .list_multiparts_uploads(Some(key), None)
+        .await
+        .context("could not list in-progress uploads")?
+        .into_iter()
+        .flat_map(|page| page.uploads)
+        .any(|u| u.key == key && u.id == state.upload_id);
+
+    if !in_progress {
+        debug!("previous upload of {key:?} is gone, starting over");
+        state.remove();
+        return Ok(None);
+    }
+
+    if state.checksum != checksum || state.chunk_size != chunk_size {
+        debug!("file or chunk size changed since last upload of {key:?}, starting over");
+        if let Err(e) = bucket.abort_upload(key, &state.upload_id).await {
+            warn!("could not abort previous upload of {key:?}: {e}");
+        }
+        state.remove();
+        return Ok(None);
+    }
+
+    Ok(Some(state))
+}
+
+fn mib(bytes: u64) -> String {
+    format!("{:.1}", bytes as f64 / (1024.0 * 1024.0))
+}
+
+fn rate(bytes: u64, elapsed: Duration) -> String {
+    let per_sec = bytes as f64 / elapsed.as_secs_f64().max(0.001);
+    format!("{} MiB/s", mib(per_sec as u64))
+}
@@ -0,0 +1,434 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use base64::prelude::{Engine, BASE64_STANDARD};
+use itertools::Itertools;
+use k8s_openapi::chrono::{DateTime, Utc};
+use s3::bucket_ops::BucketConfiguration;
+use s3::error::S3Error;
+use s3::serde_types::HeadObjectResult;
+use s3::Bucket;
+use tracing::{debug, info, trace, warn};
+
+use crate::builder::Asset;
+use crate::clients::{bucket_client, bucket_client_anonymous};
+use crate::configparser::config::S3Config;
+use crate::configparser::ChallengeConfig;
+
+use super::multipart::upload_multipart;
+use super::{asset_headers, asset_path, asset_prefix, AssetStore, UploadOutcome};
+
+/// Object metadata key holding the SHA-256 of uploaded assets
+const CHECKSUM_METADATA: &str = "sha256";
+
+/// How many characters of the file hash to use in content-addressed paths
+const CONTENT_HASH_LEN: usize = 8;
+
+/// Assets stored in an S3-compatible bucket.
+pub struct S3Store<'a> {
+    pub config: &'a S3Config,
+    bucket: &'a Bucket,
+}
+
+impl<'a> S3Store<'a> {
+    pub fn new(config: &'a S3Config) -> Result<Self> {
+        if config.multipart_chunk_size < 5 {
+            bail!("s3 multipart_chunk_size must be at least 5 (MiB)");
+        }
+        Ok(S3Store {
+            config,
+            bucket: bucket_client(config)?,
+        })
+    }
+
+    /// Create the bucket if it does not exist yet, allow anyone to download
+    /// objects under `assets/`, and allow the frontend at `frontend_url` to
+    /// fetch them from the browser.
+    pub async fn setup(&self, frontend_url: &str) -> Result<()> {
+        let name = &self.config.bucket_name;
+
+        if self.bucket.exists().await? {
+            info!("  bucket {name:?} already exists");
+        } else {
+            info!("  creating bucket {name:?}");
+            let (code, body) = match Bucket::create_with_path_style(
+                name,
+                self.bucket.region(),
+                self.bucket.credentials().await?,
+                BucketConfiguration::default(),
+            )
+            .await
+            {
+                Ok(response) => (response.response_code, response.response_text),
+                Err(S3Error::HttpFailWithBody(code, body)) => (code, body),
+                Err(e) => {
+                    return Err(e).with_context(|| format!("could not create bucket {name:?}"))
+                }
+            };
+
+            // fine if it was created by us since checking, e.g. by another deploy
+            if body.contains("BucketAlreadyOwnedByYou") {
+                info!("  bucket {name:?} is already owned by us");
+            } else if !(200..300).contains(&code) {
+                bail!("could not create bucket {name:?}: got status {code}: {body}");
+            }
+        }
+
+        // everything else in the bucket stays private
+        info!("  allowing public downloads of assets");
+        let policy = serde_json::json!({
+            "Version": "2012-10-17",
+            "Statement": [{
+                "Effect": "Allow",
+                "Principal": { "AWS": ["*"] },
+                "Action": ["s3:GetObject"],
+                "Resource": [format!("arn:aws:s3:::{name}/assets/*")],
+            }],
+        });
+        self.put_bucket_config("policy", "application/json", policy.to_string())
+            .await
+            .context("could not set public bucket policy")?;
+
+        let origin = reqwest::Url::parse(frontend_url)
+            .with_context(|| format!("bad frontend url {frontend_url:?}"))?
+            .origin()
+            .ascii_serialization();
+        info!("  allowing cross-origin downloads from {origin}");
+        let cors = format!(
+            "<CORSConfiguration><CORSRule>\
+            <AllowedOrigin>{origin}</AllowedOrigin>\
+            <AllowedMethod>GET</AllowedMethod>\
+            <AllowedMethod>HEAD</AllowedMethod>\
+            <AllowedHeader>*</AllowedHeader>\
+            <MaxAgeSeconds>3600</MaxAgeSeconds>\
+            </CORSRule></CORSConfiguration>"
+        );
+        // MinIO does not implement bucket CORS and allows all origins instead
+        if let Err(e) = self
+            .put_bucket_config("cors", "application/xml", cors)
+            .await
+        {
+            warn!("could not set bucket CORS rules, check that the frontend can download assets: {e:?}");
+        }
+
+        Ok(())
+    }
+
+    /// Download url for `path`. Private objects get a signed url that expires
+    /// after the configured time.
+    async fn url(&self, path: &str, private: bool) -> Result<String> {
+        if private {
+            self.bucket
+                .presign_get(path, self.config.private_url_expiry, None)
+                .await
+                .with_context(|| format!("could not sign url for {path:?}"))
+        } else {
+            Ok(format!("{}/{path}", self.bucket.url()))
+        }
+    }
+
+    /// Latest stored version of each of `chal`'s assets, with fresh download
+    /// urls.
+    pub async fn stored_assets(&self, chal: &ChallengeConfig) -> Result<Vec<Asset>> {
+        let mut assets = vec![];
+        for private in [false, true] {
+            let objects = self
+                .bucket
+                .list(asset_prefix(chal, private), None)
+                .await?
+                .into_iter()
+                .flat_map(|page| page.contents)
+                .collect_vec();
+
+            // superseded content-addressed versions share the filename, keep
+            // only the newest. timestamps are all RFC 3339 in UTC, so they
+            // sort as strings
+            let latest = objects
+                .iter()
+                .into_group_map_by(|o| Path::new(&o.key).file_name())
+                .into_values()
+                .filter_map(|versions| versions.into_iter().max_by_key(|o| &o.last_modified));
+
+            for object in latest {
+                // checksum is only kept in the object metadata
+                let sha256 = existing_object(self.bucket, &object.key)
+                    .await?
+                    .and_then(|head| head.metadata)
+                    .and_then(|mut m| m.remove(CHECKSUM_METADATA))
+                    .unwrap_or_else(|| {
+                        warn!("no checksum stored for {:?}", object.key);
+                        String::new()
+                    });
+                assets.push(Asset {
+                    path: PathBuf::from(&object.key),
+                    sha256,
+                    content_type: None,
+                    content_disposition: None,
+                    private,
+                    url: Some(self.url(&object.key, private).await?),
+                });
+            }
+        }
+
+        Ok(assets)
+    }
+
+    /// Set bucket subresource `kind` (e.g. `policy` for `PUT /bucket?policy`) to `body`.
+    // rust-s3 does not support bucket policies, and sends CORS rules without
+    // a body, so do these ourselves through a presigned request instead
+    async fn put_bucket_config(&self, kind: &str, content_type: &str, body: String) -> Result<()> {
+        let url = self
+            .bucket
+            .presign_put(
+                "/",
+                60,
+                None,
+                Some(HashMap::from([(kind.to_string(), String::new())])),
+            )
+            .await?;
+        let md5 = BASE64_STANDARD.encode(md5::compute(&body).0);
+
+        trace!("setting bucket {kind}: {body}");
+        let response = reqwest::Client::new()
+            .put(url)
+            .header("content-type", content_type)
+            .header("content-md5", md5)
+            .body(body)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            bail!(
+                "bucket returned {}: {}",
+                response.status(),
+                response.text().await?
+            );
+        }
+
+        Ok(())
+    }
+}
+
+impl AssetStore for S3Store<'_> {
+    async fn upload(
+        &self,
+        chal: &ChallengeConfig,
+        asset: &Asset,
+    ) -> Result<(Asset, UploadOutcome)> {
+        let file = &asset.path;
+        let checksum = &asset.sha256;
+
+        let content_hash = self
+            .config
+            .content_addressed
+            .then(|| &checksum[..CONTENT_HASH_LEN]);
+        let path_in_bucket = asset_path(chal, asset, content_hash)
+            .to_string_lossy()
+            .to_string();
+
+        let (content_type, content_disposition) = asset_headers(asset);
+
+        let uploaded = Asset {
+            path: PathBuf::from(&path_in_bucket),
+            url: Some(self.url(&path_in_bucket, asset.private).await?),
+            ..asset.clone()
+        };
+
+        // headers are part of what was uploaded, so changing them needs a reupload
+        if let Some(existing) = existing_object(self.bucket, &path_in_bucket).await? {
+            let existing_checksum = existing
+                .metadata
+                .and_then(|mut m| m.remove(CHECKSUM_METADATA));
+            if existing_checksum.as_ref() == Some(checksum)
+                && existing.content_type.as_ref() == Some(&content_type)
+                && existing.content_disposition.as_ref() == Some(&content_disposition)
+            {
+                trace!("{:?} is unchanged at {:?}, skipping", file, &path_in_bucket);
+                return Ok((uploaded, UploadOutcome::Skipped));
+            }
+        }
+
+        trace!("uploading {:?} to bucket path {:?}", file, &path_in_bucket);
+
+        // store the checksum alongside the object to compare against next time
+        let mut bucket = self.bucket.clone();
+        bucket.add_header(&format!("x-amz-meta-{CHECKSUM_METADATA}"), checksum);
+        bucket.add_header("content-disposition", &content_disposition);
+
+        // large files are uploaded in parts to report progress and resume
+        let chunk_size = self.config.multipart_chunk_size * 1024 * 1024;
+        if fs::metadata(file)?.len() > chunk_size {
+            let bytes = upload_multipart(
+                self.bucket,
+                &bucket,
+                file,
+                &path_in_bucket,
+                checksum,
+                &content_type,
+                chunk_size,
+            )
+            .await?;
+            return Ok((uploaded, UploadOutcome::Uploaded(bytes)));
+        }
+
+        let mut asset_file = tokio::fs::File::open(file).await?;
+        let r = bucket
+            .put_object_stream_with_content_type(&mut asset_file, &path_in_bucket, &content_type)
+            .await?;
+        trace!("uploaded {} bytes for file {:?}", r.uploaded_bytes(), file);
+
+        Ok((uploaded, UploadOutcome::Uploaded(r.uploaded_bytes() as u64)))
+    }
+
+    async fn finish_challenge(&self, chal: &ChallengeConfig, uploaded: &[Asset]) -> Result<()> {
+        if self.config.content_addressed {
+            let current = uploaded.iter().map(|a| &a.path).collect_vec();
+            remove_superseded(self.bucket, self.config, chal, &current).await?;
+        }
+        Ok(())
+    }
+
+    async fn list(&self) -> Result<Vec<String>> {
+        let mut keys = vec![];
+        for prefix in ["assets/", "private/"] {
+            let objects = self
+                .bucket
+                .list(prefix.to_string(), None)
+                .await
+                .context("could not list bucket contents")?;
+            keys.extend(
+                objects
+                    .into_iter()
+                    .flat_map(|page| page.contents)
+                    .map(|object| object.key),
+            );
+        }
+
+        trace!("found uploaded assets: {keys:?}");
+        Ok(keys)
+    }
+
+    async fn delete(&self, path: &str) -> Result<()> {
+        self.bucket
+            .delete_object(path)
+            .await
+            .with_context(|| format!("could not delete {path:?} from bucket"))?;
+        Ok(())
+    }
+
+    async fn check(&self) -> Result<()> {
+        let bucket = self.bucket;
+
+        if !bucket.exists().await? {
+            bail!(
+                "bucket {} does not exist! (create it with `bucket-setup`)",
+                self.config.bucket_name
+            );
+        }
+
+        // try uploading file to bucket, under assets/ since only that is public
+        debug!("uploading test file to bucket");
+        let test_file = ("assets/beavercds-test-file", "access test file!");
+        bucket
+            .put_object_with_content_type(test_file.0, test_file.1.as_bytes(), "text/plain")
+            .await
+            .with_context(|| {
+                format!(
+                    "could not upload to asset bucket {:?}",
+                    self.config.bucket_name
+                )
+            })?;
+
+        // download it to check
+        debug!("downloading test file");
+        let from_bucket = bucket.get_object(test_file.0).await?;
+        if from_bucket.bytes() != test_file.1 {
+            bail!("uploaded test file contents do not match, somehow!?");
+        }
+
+        // download as anonymous to check public access
+        debug!("downloading test file as public user");
+        let public_bucket = bucket_client_anonymous(self.config)?;
+        let from_public = public_bucket
+            .get_object(test_file.0)
+            .await
+            .with_context(|| {
+                anyhow!(
+                    "public download from asset bucket {:?} failed",
+                    self.config.bucket_name
+                )
+            })?;
+        if from_public.bytes() != test_file.1 {
+            bail!("contents of public bucket do not match uploaded file");
+        }
+
+        // clean up test file after checks
+        bucket.delete_object(test_file.0).await?;
+
+        Ok(())
+    }
+}
+
+/// Get the headers of an already-uploaded object, if it exists.
+async fn existing_object(bucket: &Bucket, path: &str) -> Result<Option<HeadObjectResult>> {
+    match bucket.head_object(path).await {
+        Err(S3Error::HttpFailWithBody(404, _)) => Ok(None),
+        r => r
+            .map(|(head, _)| Some(head))
+            .with_context(|| format!("could not check existing object {path:?}")),
+    }
+}
+
+/// Remove previous versions of content-addressed files in `current` once the
+/// current version has been around for the configured grace period, so
+/// players that already loaded the old URL can still download it for a bit.
+async fn remove_superseded(
+    bucket: &Bucket,
+    config: &S3Config,
+    chal: &ChallengeConfig,
+    current: &[&PathBuf],
+) -> Result<()> {
+    let mut objects = vec![];
+    for private in [false, true] {
+        let pages = bucket.list(asset_prefix(chal, private), None).await?;
+        objects.extend(pages.into_iter().flat_map(|page| page.contents));
+    }
+
+    let now = Utc::now();
+    for current_path in current {
+        let current_key = current_path.to_string_lossy();
+        let Some(current_object) = objects.iter().find(|o| o.key == current_key) else {
+            continue;
+        };
+        let uploaded_at = DateTime::parse_from_rfc3339(&current_object.last_modified)
+            .with_context(|| format!("bad modified time for {current_key:?}"))?;
+        let expired = (now - uploaded_at.to_utc()).num_seconds() >= config.superseded_grace as i64;
+
+        // older versions have the same filename under a different hash, and
+        // are both public or both private
+        let filename = current_path.file_name();
+        let root = current_path.iter().next();
+        for old in objects.iter().filter(|o| {
+            let old_path = Path::new(&o.key);
+            o.key != current_key
+                && old_path.file_name() == filename
+                && old_path.iter().next() == root
+        }) {
+            if expired {
+                debug!("removing superseded asset {:?}", old.key);
+                bucket
+                    .delete_object(&old.key)
+                    .await
+                    .with_context(|| format!("could not delete {:?}", old.key))?;
+            } else {
+                debug!(
+                    "keeping superseded asset {:?} until grace period is over",
+                    old.key
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -1,20 +1,259 @@
-use std::path::PathBuf;
+// Challenge sync with the rCTF frontend, through its admin API. Challenges are
+// stored on the frontend under their slug, so only challenges from this repo
+// are ever changed or removed.
 
-use anyhow::{anyhow, bail, Context, Error, Ok, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use itertools::Itertools;
-use tracing::{debug, error, info, trace, warn};
+use reqwest::Method;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, trace, warn};
 
-use crate::builder::BuildResult;
+use crate::builder::Asset;
 use crate::configparser::config::ProfileConfig;
-use crate::configparser::{enabled_challenges, get_config, get_profile_config, ChallengeConfig};
+use crate::configparser::{
+    enabled_challenges, get_challenges, get_config, get_profile_config, ChallengeConfig,
+};
+use crate::deploy::assets::CHECKSUMS_FILENAME;
 
-/// Sync deployed challenges with rCTF frontend
+/// Client for the rCTF admin API.
+pub struct FrontendApi {
+    client: reqwest::Client,
+    url: String,
+    token: String,
+}
+
+/// rCTF API response wrapper
+#[derive(Debug, Deserialize)]
+struct Response<T> {
+    kind: String,
+    message: String,
+    data: Option<T>,
+}
+
+/// A challenge as sent to rCTF.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Challenge {
+    name: String,
+    description: String,
+    category: String,
+    author: String,
+    files: Vec<File>,
+    points: Points,
+    /// Left out for flags rCTF cannot check, so it keeps whatever is set there
+    #[serde(skip_serializing_if = "Option::is_none")]
+    flag: Option<String>,
+    tiebreak_eligible: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct File {
+    name: String,
+    url: String,
+}
+
+#[derive(Debug, Serialize)]
+struct Points {
+    min: i64,
+    max: i64,
+}
+
+/// A challenge as listed by rCTF. Only the id is needed.
+#[derive(Debug, Deserialize)]
+struct ListedChallenge {
+    id: String,
+}
+
+impl FrontendApi {
+    pub fn new(profile: &ProfileConfig) -> Result<Self> {
+        Ok(FrontendApi {
+            client: reqwest::Client::builder().build()?,
+            url: profile.frontend_url.trim_end_matches('/').to_string(),
+            token: profile.frontend_token.clone(),
+        })
+    }
+
+    /// Call admin API `endpoint` with `body`, returning its response data if
+    /// it has any.
+    pub async fn call<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        endpoint: &str,
+        body: Option<serde_json::Value>,
+    ) -> Result<Option<T>> {
+        let url = format!("{}/api/v1/admin/{endpoint}", self.url);
+        trace!("calling frontend api {method} {url}");
+
+        let mut request = self.client.request(method, &url).bearer_auth(&self.token);
+        if let Some(body) = body {
+            request = request.json(&body);
+        }
+
+        let response: Response<T> = request
+            .send()
+            .await
+            .with_context(|| format!("could not reach frontend at {url}"))?
+            .json()
+            .await
+            .with_context(|| format!("bad response from frontend at {url}"))?;
+
+        // rCTF response kinds are `good...` or `bad...`
+        if !response.kind.starts_with("good") {
+            bail!("frontend returned {}: {}", response.kind, response.message);
+        }
+        Ok(response.data)
+    }
+
+    /// Ids of challenges on the frontend that are from this repo.
+    pub async fn repo_challenges(&self) -> Result<Vec<String>> {
+        let repo_ids = get_challenges()
+            .map_err(|errs| anyhow!("{errs:?}"))?
+            .iter()
+            .map(|c| c.slugify())
+            .collect_vec();
+
+        let listed: Vec<ListedChallenge> = self
+            .call(Method::GET, "challs", None)
+            .await?
+            .unwrap_or_default();
+
+        Ok(listed
+            .into_iter()
+            .map(|c| c.id)
+            .filter(|id| repo_ids.contains(id))
+            .sorted()
+            .collect())
+    }
+
+    /// Remove challenge `id` from the frontend.
+    pub async fn delete_challenge(&self, id: &str) -> Result<()> {
+        self.call::<serde_json::Value>(Method::DELETE, &format!("challs/{id}"), None)
+            .await
+            .with_context(|| format!("could not remove chal {id} from frontend"))?;
+        Ok(())
+    }
+}
+
+/// Sync deployed challenges with rCTF frontend. Each challenge is created or
+/// updated with its uploaded `assets`. If `prune` is set, challenges from
+/// this repo that are no longer enabled in the profile are removed.
 pub async fn update_frontend(
     profile_name: &str,
-    build_results: &[(&ChallengeConfig, BuildResult)],
+    challenges: &[(&ChallengeConfig, Vec<Asset>)],
+    prune: bool,
 ) -> Result<()> {
     let profile = get_profile_config(profile_name)?;
-    let enabled_challenges = enabled_challenges(profile_name)?;
+    let api = FrontendApi::new(profile)?;
+
+    info!(
+        "updating challenges on frontend {}...",
+        profile.frontend_url
+    );
+    for (chal, assets) in challenges {
+        let id = chal.slugify();
+        debug!("  updating chal {id}");
+
+        let data = challenge_data(chal, assets)
+            .with_context(|| format!("could not build frontend data for chal {id}"))?;
+        api.call::<serde_json::Value>(
+            Method::PUT,
+            &format!("challs/{id}"),
+            Some(serde_json::json!({ "data": data })),
+        )
+        .await
+        .with_context(|| format!("could not update chal {id} on frontend"))?;
+    }
+
+    if !prune {
+        return Ok(());
+    }
+
+    let enabled = enabled_challenges(profile_name)?
+        .iter()
+        .map(|c| c.slugify())
+        .collect_vec();
+    for id in api.repo_challenges().await? {
+        if !enabled.contains(&id) {
+            info!("  removing disabled chal {id} from frontend");
+            api.delete_challenge(&id).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Frontend challenge data for `chal`, with its uploaded `assets`.
+fn challenge_data(chal: &ChallengeConfig, assets: &[Asset]) -> Result<Challenge> {
+    let points = get_config()?
+        .points
+        .iter()
+        .find(|p| p.difficulty == chal.difficulty)
+        .ok_or_else(|| {
+            anyhow!(
+                "no points are configured for difficulty {}",
+                chal.difficulty
+            )
+        })?;
+
+    let files = assets
+        .iter()
+        .map(|a| {
+            let name = a.path.file_name().unwrap().to_string_lossy().to_string();
+            let url = a
+                .url
+                .clone()
+                .ok_or_else(|| anyhow!("asset {name:?} has no download url"))?;
+            Ok(File { name, url })
+        })
+        .collect::<Result<_>>()?;
+
+    Ok(Challenge {
+        name: chal.name.clone(),
+        description: format!("{}{}", chal.description, checksums_description(assets)),
+        category: chal.category.clone(),
+        author: chal.author.clone(),
+        files,
+        points: Points {
+            min: points.min,
+            max: points.max,
+        },
+        flag: frontend_flag(chal)?,
+        tiebreak_eligible: true,
+    })
+}
+
+/// Flag to give rCTF for `chal`. rCTF only compares submissions against a
+/// fixed string, so regex and verifier flags are not sent and have to be set
+/// up on the frontend by hand.
+fn frontend_flag(chal: &ChallengeConfig) -> Result<Option<String>> {
+    let flag = chal.static_flag()?;
+    if flag.is_none() {
+        warn!(
+            "  chal {:?} does not have a fixed flag, not setting it on the frontend",
+            chal.directory
+        );
+    }
+    Ok(flag)
+}
+
+/// Markdown list of asset checksums to add to a challenge's description, so
+/// players can check their downloads.
+fn checksums_description(assets: &[Asset]) -> String {
+    let lines = assets
+        .iter()
+        .filter(|a| a.path.file_name().is_some_and(|f| f != CHECKSUMS_FILENAME))
+        .map(|a| {
+            format!(
+                "- `{}`: `{}`",
+                a.path.file_name().unwrap().to_string_lossy(),
+                a.sha256
+            )
+        })
+        .join("\n");
 
-    todo!()
+    if lines.is_empty() {
+        return String::new();
+    }
+    format!("\n\nSHA-256 checksums:\n{lines}\n")
 }
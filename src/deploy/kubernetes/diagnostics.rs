@@ -0,0 +1,158 @@
+// Gather details about why a challenge deployment is not becoming ready, to
+// show alongside rollout timeout errors.
+
+use std::fmt::Write;
+
+use anyhow::{Context, Result};
+use itertools::Itertools;
+use k8s_openapi::api::apps::v1::Deployment;
+use k8s_openapi::api::core::v1::{Event, Pod};
+use kube::api::{ListParams, LogParams};
+use kube::ResourceExt;
+use tracing::{debug, trace};
+
+/// How many recent events to show per pod
+const EVENT_COUNT: usize = 5;
+/// How many log lines to show per failing container
+const LOG_TAIL_LINES: i64 = 20;
+
+/// Describe the state of all pods in Deployment `name`: init and regular
+/// container failure reasons, recent events, and recent logs of any failing
+/// containers.
+pub async fn deployment_diagnostics(
+    kube: &kube::Client,
+    namespace: &str,
+    name: &str,
+) -> Result<String> {
+    debug!("collecting diagnostics for deployment {namespace}/{name}");
+
+    let deployments: kube::Api<Deployment> = kube::Api::namespaced(kube.clone(), namespace);
+    let pods: kube::Api<Pod> = kube::Api::namespaced(kube.clone(), namespace);
+    let events: kube::Api<Event> = kube::Api::namespaced(kube.clone(), namespace);
+
+    // find pods via the deployment's selector labels
+    let deployment = deployments.get(name).await?;
+    let selector = deployment
+        .spec
+        .and_then(|s| s.selector.match_labels)
+        .unwrap_or_default()
+        .iter()
+        .map(|(k, v)| format!("{k}={v}"))
+        .join(",");
+    trace!("using pod selector {selector:?}");
+
+    let pod_list = pods
+        .list(&ListParams::default().labels(&selector))
+        .await
+        .context("could not list deployment pods")?;
+
+    let mut out = String::new();
+    if pod_list.items.is_empty() {
+        writeln!(out, "deployment {name} has no pods")?;
+    }
+
+    for pod in pod_list.items.iter() {
+        let pod_name = pod.name_any();
+        let status = pod.status.clone().unwrap_or_default();
+        writeln!(
+            out,
+            "pod {pod_name}: {}",
+            status.phase.as_deref().unwrap_or("Unknown")
+        )?;
+
+        // container states. Init containers run first, so if one of them is
+        // failing the regular containers will just be waiting on it.
+        let statuses = status
+            .init_container_statuses
+            .unwrap_or_default()
+            .into_iter()
+            .map(|cs| ("init container", cs))
+            .chain(
+                status
+                    .container_statuses
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|cs| ("container", cs)),
+            );
+        let mut failing = vec![];
+        for (kind, cs) in statuses {
+            let state = cs.state.clone().unwrap_or_default();
+            let reason = if let Some(waiting) = state.waiting {
+                format!(
+                    "waiting: {} {}",
+                    waiting.reason.unwrap_or_default(),
+                    waiting.message.unwrap_or_default()
+                )
+            } else if let Some(terminated) = state.terminated {
+                format!(
+                    "terminated: {} (exit code {})",
+                    terminated.reason.unwrap_or_default(),
+                    terminated.exit_code
+                )
+            } else {
+                "running".to_string()
+            };
+            writeln!(
+                out,
+                "  {kind} {}: {} (ready: {}, restarts: {})",
+                cs.name,
+                reason.trim(),
+                cs.ready,
+                cs.restart_count
+            )?;
+
+            if !cs.ready {
+                failing.push((kind, cs));
+            }
+        }
+
+        // recent events for this pod
+        let pod_events = events
+            .list(&ListParams::default().fields(&format!("involvedObject.name={pod_name}")))
+            .await
+            .context("could not list pod events")?;
+        let recent = pod_events
+            .items
+            .iter()
+            .sorted_by_key(|e| e.last_timestamp.clone().map(|t| t.0))
+            .rev()
+            .take(EVENT_COUNT)
+            .collect_vec();
+        if !recent.is_empty() {
+            writeln!(out, "  recent events:")?;
+            for event in recent.iter().rev() {
+                writeln!(
+                    out,
+                    "    {} {}: {}",
+                    event.type_.as_deref().unwrap_or_default(),
+                    event.reason.as_deref().unwrap_or_default(),
+                    event.message.as_deref().unwrap_or_default().trim()
+                )?;
+            }
+        }
+
+        // logs from containers that are not ready
+        for (kind, cs) in failing {
+            let params = LogParams {
+                container: Some(cs.name.clone()),
+                tail_lines: Some(LOG_TAIL_LINES),
+                // show logs from the crashed run if it has restarted
+                previous: cs.restart_count > 0,
+                ..Default::default()
+            };
+            match pods.logs(&pod_name, &params).await {
+                Ok(logs) if !logs.trim().is_empty() => {
+                    writeln!(out, "  last logs from {kind} {}:", cs.name)?;
+                    for line in logs.lines() {
+                        writeln!(out, "    | {line}")?;
+                    }
+                }
+                // containers that never started will not have any logs
+                Ok(_) => (),
+                Err(e) => trace!("could not get logs for {pod_name}/{}: {e:?}", cs.name),
+            }
+        }
+    }
+
+    Ok(out)
+}
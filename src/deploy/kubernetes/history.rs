@@ -0,0 +1,330 @@
+// Deploy history for challenges, stored as one ConfigMap per revision in each
+// challenge's namespace, so a challenge's history is not limited by the size
+// limit of a single object. Each revision keeps the manifests that were
+// applied and the image digests that were deployed, so that a challenge can
+// be rolled back to a previous revision without rebuilding anything.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use itertools::Itertools;
+use k8s_openapi::api::core::v1::ConfigMap;
+use kube::api::{DeleteParams, ListParams, ObjectMeta, Patch, PatchParams};
+use kube::ResourceExt;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{debug, info, trace, warn};
+
+use crate::builder::{docker, BuildResult, TagWithSource};
+use crate::clients::{kube_client, multidoc_deserialize};
+use crate::configparser::{get_profile_config, ChallengeConfig};
+use crate::deploy::lock::DeployLock;
+use crate::utils::operator_identity;
+
+use super::{apply_and_wait, RenderedManifest};
+
+/// Name prefix of the ConfigMaps holding each revision, e.g. `rcds-history-3`
+pub const HISTORY_CONFIGMAP_PREFIX: &str = "rcds-history";
+
+/// Label marking history ConfigMaps
+const HISTORY_LABEL: &str = "rctf/history";
+
+/// How many revisions to keep for each challenge
+const HISTORY_LIMIT: usize = 10;
+
+/// A single deploy of a challenge.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Revision {
+    pub revision: u64,
+    /// When this revision was deployed, in RFC 3339 format
+    pub timestamp: String,
+    /// Who deployed this revision, as `user@host`
+    pub operator: String,
+    /// Commit of the challenge repo at deploy time, if deployed from a git repo
+    pub git_commit: Option<String>,
+    /// Deployed image tags mapped to their pinned `repo@sha256:...` reference
+    pub images: BTreeMap<String, String>,
+    /// SHA-256 hash of all applied manifests
+    pub manifests_hash: String,
+    /// The revision that was restored, if this revision was a rollback
+    pub rollback_of: Option<u64>,
+    /// The manifests that were applied, in apply order
+    pub manifests: Vec<RenderedManifest>,
+}
+
+/// Look up the pinned digests for all images built for a challenge.
+pub async fn image_digests(build_result: &BuildResult) -> Result<BTreeMap<String, String>> {
+    let mut digests = BTreeMap::new();
+
+    // upstream images are not ours to pin, only record built ones
+    for tag in build_result.tags.iter() {
+        if let TagWithSource::Built(tag) = tag {
+            match docker::image_digest(tag).await? {
+                Some(digest) => {
+                    digests.insert(tag.clone(), digest);
+                }
+                None => warn!("no digest found for image {tag:?}, not pinning for rollback"),
+            }
+        }
+    }
+
+    Ok(digests)
+}
+
+/// Fetch all recorded revisions for `chal`, oldest first.
+pub async fn get_history(kube: &kube::Client, chal: &ChallengeConfig) -> Result<Vec<Revision>> {
+    let api: kube::Api<ConfigMap> = kube::Api::namespaced(kube.clone(), &namespace_for(chal));
+
+    api.list(&ListParams::default().labels(HISTORY_LABEL))
+        .await?
+        .into_iter()
+        .map(|cm| {
+            let rev = cm
+                .data
+                .as_ref()
+                .and_then(|d| d.get("revision"))
+                .ok_or_else(|| anyhow!("history configmap {:?} has no revision", cm.name_any()))?;
+            serde_yml::from_str::<Revision>(rev).map_err(|e| anyhow!(e))
+        })
+        .collect::<Result<Vec<_>>>()
+        .map(|revs| revs.into_iter().sorted_by_key(|r| r.revision).collect())
+        .context("could not parse deploy history")
+}
+
+/// Record `manifests` and `images` as the newest revision for `chal`.
+pub async fn record_revision(
+    kube: &kube::Client,
+    chal: &ChallengeConfig,
+    manifests: Vec<RenderedManifest>,
+    images: BTreeMap<String, String>,
+    rollback_of: Option<u64>,
+) -> Result<Revision> {
+    let history = get_history(kube, chal).await?;
+
+    let revision = Revision {
+        revision: history.last().map(|r| r.revision + 1).unwrap_or(1),
+        timestamp: chrono::Utc::now().to_rfc3339(),
+        operator: operator_identity(),
+        git_commit: git_commit(),
+        images,
+        manifests_hash: hash_manifests(&manifests),
+        rollback_of,
+        manifests,
+    };
+    debug!(
+        "recording revision {} for chal {:?}",
+        revision.revision, chal.directory
+    );
+
+    let name = format!("{HISTORY_CONFIGMAP_PREFIX}-{}", revision.revision);
+    let configmap = ConfigMap {
+        metadata: ObjectMeta {
+            name: Some(name.clone()),
+            namespace: Some(namespace_for(chal)),
+            labels: Some(BTreeMap::from([
+                (HISTORY_LABEL.to_string(), "true".to_string()),
+                (
+                    "app.kubernetes.io/managed-by".to_string(),
+                    "rcds".to_string(),
+                ),
+            ])),
+            ..Default::default()
+        },
+        data: Some(BTreeMap::from([(
+            "revision".to_string(),
+            serde_yml::to_string(&revision)?,
+        )])),
+        ..Default::default()
+    };
+
+    let api: kube::Api<ConfigMap> = kube::Api::namespaced(kube.clone(), &namespace_for(chal));
+    api.patch(
+        &name,
+        &PatchParams::apply("beavercds").force(),
+        &Patch::Apply(&configmap),
+    )
+    .await?;
+
+    // only keep the most recent revisions, counting the new one
+    let expired = history.len().saturating_sub(HISTORY_LIMIT - 1);
+    for old in history.iter().take(expired) {
+        let old_name = format!("{HISTORY_CONFIGMAP_PREFIX}-{}", old.revision);
+        debug!("removing old revision {old_name:?}");
+        api.delete(&old_name, &DeleteParams::default()).await?;
+    }
+
+    Ok(revision)
+}
+
+/// Re-apply a previous revision of `chal`, either revision `to` or the one
+/// before the latest. Images are pinned to the digests deployed with that
+/// revision, so nothing is rebuilt. Aborts if `lock` is lost partway through.
+pub async fn rollback_challenge(
+    profile_name: &str,
+    chal: &ChallengeConfig,
+    to: Option<u64>,
+    lock: &DeployLock,
+) -> Result<Revision> {
+    let profile = get_profile_config(profile_name)?;
+    let kube = kube_client(profile).await?;
+
+    let history = get_history(&kube, chal).await?;
+    trace!(
+        "history for chal {:?}: revisions {:?}",
+        chal.directory,
+        history.iter().map(|r| r.revision).collect_vec()
+    );
+
+    let target = match to {
+        Some(rev) => history.iter().find(|r| r.revision == rev).ok_or_else(|| {
+            anyhow!(
+                "revision {rev} not found for chal {:?} (available: {})",
+                chal.directory,
+                history.iter().map(|r| r.revision).join(", ")
+            )
+        })?,
+        // default to the revision before the current one
+        None => match history.len() {
+            0 | 1 => bail!(
+                "chal {:?} has no previous revision to roll back to",
+                chal.directory
+            ),
+            n => &history[n - 2],
+        },
+    };
+
+    info!(
+        "  rolling back chal {:?} to revision {} (deployed {} by {}, commit {})",
+        chal.directory,
+        target.revision,
+        target.timestamp,
+        target.operator,
+        target.git_commit.as_deref().unwrap_or("unknown")
+    );
+
+    let manifests = pin_images(&target.manifests, &target.images)
+        .context("could not pin images to their deployed digests")?;
+    let wait_timeout = Duration::from_secs(profile.deploy_timeout);
+    for manifest in &manifests {
+        lock.check()?;
+        apply_and_wait(&kube, chal, manifest, wait_timeout).await?;
+    }
+
+    record_revision(
+        &kube,
+        chal,
+        manifests,
+        target.images.clone(),
+        Some(target.revision),
+    )
+    .await
+    .with_context(|| {
+        format!(
+            "could not record deploy history for chal {:?}",
+            chal.directory
+        )
+    })
+}
+
+/// Replace image tags of the containers in `manifests`' Deployments with
+/// their pinned digest references. Fails if there are images to pin but none
+/// of them were found, since the rollback would then silently deploy whatever
+/// the mutable tags point to now.
+pub(crate) fn pin_images(
+    manifests: &[RenderedManifest],
+    images: &BTreeMap<String, String>,
+) -> Result<Vec<RenderedManifest>> {
+    if images.is_empty() {
+        return Ok(manifests.to_vec());
+    }
+
+    let mut pinned = 0;
+    let manifests = manifests
+        .iter()
+        .map(|m| {
+            let mut docs = multidoc_deserialize(&m.yaml)?;
+            let mut changed = false;
+
+            for doc in docs.iter_mut() {
+                if doc.get("kind").and_then(|k| k.as_str()) != Some("Deployment") {
+                    continue;
+                }
+                let Some(pod_spec) = doc
+                    .get_mut("spec")
+                    .and_then(|s| s.get_mut("template"))
+                    .and_then(|t| t.get_mut("spec"))
+                else {
+                    continue;
+                };
+
+                for list in ["initContainers", "containers"] {
+                    let containers = pod_spec
+                        .get_mut(list)
+                        .and_then(|c| c.as_sequence_mut())
+                        .into_iter()
+                        .flatten();
+                    for image in containers.filter_map(|c| c.get_mut("image")) {
+                        if let Some(digest) = image.as_str().and_then(|i| images.get(i)) {
+                            trace!("pinning image {image:?} to {digest:?}");
+                            *image = digest.as_str().into();
+                            changed = true;
+                            pinned += 1;
+                        }
+                    }
+                }
+            }
+
+            // leave manifests without images exactly as they were
+            if !changed {
+                return Ok(m.clone());
+            }
+            let yaml = docs
+                .iter()
+                .map(serde_yml::to_string)
+                .collect::<Result<Vec<_>, _>>()?
+                .join("---\n");
+            Ok(RenderedManifest { yaml, ..m.clone() })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    if pinned == 0 {
+        bail!(
+            "could not find any of the deployed images {:?} in the manifests to pin them",
+            images.keys().collect_vec()
+        );
+    }
+
+    Ok(manifests)
+}
+
+/// SHA-256 over all manifests, in order.
+fn hash_manifests(manifests: &[RenderedManifest]) -> String {
+    let mut hasher = Sha256::new();
+    for m in manifests {
+        hasher.update(m.yaml.as_bytes());
+    }
+    hex::encode(hasher.finalize())
+}
+
+/// Current commit of the challenge repo, marked if there are uncommitted changes.
+fn git_commit() -> Option<String> {
+    let commit = duct::cmd!("git", "rev-parse", "HEAD")
+        .stderr_null()
+        .read()
+        .ok()?;
+    let dirty = duct::cmd!("git", "status", "--porcelain")
+        .stderr_null()
+        .read()
+        .is_ok_and(|s| !s.trim().is_empty());
+
+    Some(if dirty {
+        format!("{commit}-dirty")
+    } else {
+        commit
+    })
+}
+
+fn namespace_for(chal: &ChallengeConfig) -> String {
+    format!("rcds-{}", chal.slugify())
+}
@@ -1,21 +1,29 @@
 use std::path::PathBuf;
 use std::time::Duration;
 
-use anyhow::{anyhow, bail, Context, Error, Ok, Result};
+use anyhow::{anyhow, bail, Context, Error, Result};
 use itertools::Itertools;
+use kube::ResourceExt;
 use minijinja;
 use tokio::time::timeout;
 use tracing::{debug, error, info, trace, warn};
 
 use crate::builder::BuildResult;
 use crate::clients::{apply_manifest_yaml, kube_client, wait_for_status};
-use crate::configparser::challenge::ExposeType;
 use crate::configparser::config::ProfileConfig;
 use crate::configparser::{get_config, get_profile_config, ChallengeConfig};
+use crate::deploy::lock::DeployLock;
 use crate::utils::TryJoinAll;
 
+pub mod diagnostics;
+pub mod history;
+pub mod render;
 pub mod templates;
 
+pub use render::{
+    render_challenge, render_pods, write_rendered_challenges, Instance, RenderedManifest,
+};
+
 /// How and where a challenge was deployed/exposed at
 pub struct DeployResult {
     // challenges could have multiple exposed services
@@ -27,10 +35,12 @@ pub enum PodDeployResult {
     Tcp { port: usize },
 }
 
-/// Render challenge manifest templates and apply to cluster
+/// Render challenge manifest templates and apply to cluster. Aborts if `lock`
+/// is lost partway through.
 pub async fn deploy_challenges(
     profile_name: &str,
     build_results: &[(&ChallengeConfig, BuildResult)],
+    lock: &DeployLock,
 ) -> Result<Vec<DeployResult>> {
     let profile = get_profile_config(profile_name)?;
 
@@ -49,10 +59,11 @@ pub async fn deploy_challenges(
 
     let results = build_results
         .iter()
-        .map(|(chal, _)| deploy_single_challenge(profile_name, chal))
+        .map(|(chal, build_result)| deploy_single_challenge(profile_name, chal, build_result, lock))
         .try_join_all()
         .await?;
 
+    lock.check()?;
     update_ingress_tcp().await?;
 
     Ok(results)
@@ -65,143 +76,98 @@ pub async fn deploy_challenges(
 async fn deploy_single_challenge(
     profile_name: &str,
     chal: &ChallengeConfig,
+    build_result: &BuildResult,
+    lock: &DeployLock,
 ) -> Result<DeployResult> {
     info!("  deploying chal {:?}...", chal.directory);
-    // render templates
 
     let profile = get_profile_config(profile_name)?;
 
     let kube = kube_client(profile).await?;
 
-    let ns_manifest = minijinja::render!(
-        templates::CHALLENGE_NAMESPACE,
-        chal, slug => chal.slugify()
-    );
-    trace!("NAMESPACE:\n{}", ns_manifest);
+    let manifests = render_challenge(profile_name, chal)?;
 
-    debug!("applying namespace for chal {:?}", chal.directory);
+    let results = DeployResult { exposed: vec![] };
 
-    // apply namespace manifest
-    apply_manifest_yaml(&kube, &ns_manifest)
-        .await?
-        .iter()
-        // and then wait for it to be ready
-        .map(|object| wait_for_status(&kube, object))
-        .try_join_all()
-        .await?;
+    let wait_timeout = Duration::from_secs(profile.deploy_timeout);
+    for manifest in &manifests {
+        lock.check()?;
+        apply_and_wait(&kube, chal, manifest, wait_timeout).await?;
+    }
 
-    let results = DeployResult { exposed: vec![] };
+    // TODO: record exposed tcp ports and http domains in results
+    // expose_results.exposed.push(PodDeployResult::Tcp { port: tcp_ports[0]. });
+
+    // save what was deployed for later rollbacks. secrets are not stored in
+    // history, so rollbacks keep the current secret values
+    let images = history::image_digests(build_result).await?;
+    let manifests = manifests.into_iter().filter(|m| !m.sensitive).collect();
+    history::record_revision(&kube, chal, manifests, images, None)
+        .await
+        .with_context(|| {
+            format!(
+                "could not record deploy history for chal {:?}",
+                chal.directory
+            )
+        })?;
 
-    for pod in &chal.pods {
-        let pod_image = chal.container_tag_for_pod(profile_name, &pod.name)?;
-        let depl_manifest = minijinja::render!(
-            templates::CHALLENGE_DEPLOYMENT,
-            chal, pod, pod_image, profile_name, slug => chal.slugify(),
-        );
-        trace!("DEPLOYMENT:\n{}", depl_manifest);
-
-        debug!(
-            "applying deployment for chal {:?} pod {:?}",
-            chal.directory, pod.name
-        );
-        let depl = apply_manifest_yaml(&kube, &depl_manifest).await?;
-        for object in depl {
-            // wait for objects to be ready, with 5m timeout
-            timeout(Duration::from_secs(5 * 60), wait_for_status(&kube, &object))
-                .await
-                // timeout wraps with another Result
-                .with_context(|| {
-                    format!(
-                        "timed out waiting for chal {:?} pod {:?} deployment to become ready",
-                        chal.directory, pod.name
-                    )
-                })?
-                // inner result from wait_for_status
-                .with_context(|| {
-                    format!(
-                        "failed to get status for chal {:?} pod {:?} deployment",
-                        chal.directory, pod.name
-                    )
-                })?;
-        }
-
-        // tcp and http exposes need to he handled separately, so separate them by type
-        let (tcp_ports, http_ports): (Vec<_>, Vec<_>) = pod
-            .ports
-            .iter()
-            .partition(|p| matches!(p.expose, ExposeType::Tcp(_)));
-
-        if !tcp_ports.is_empty() {
-            let tcp_manifest = minijinja::render!(
-                templates::CHALLENGE_SERVICE_TCP,
-                chal, pod, tcp_ports, slug => chal.slugify(), domain => profile.challenges_domain
-            );
-            trace!("TCP SERVICE:\n{}", tcp_manifest);
-
-            debug!(
-                "applying tcp service for chal {:?} pod {:?}",
-                chal.directory, pod.name
-            );
-            let tcp = apply_manifest_yaml(&kube, &tcp_manifest).await?;
-            for object in tcp {
-                // wait for objects to be ready, with 5m timeout
-                timeout(Duration::from_secs(5 * 60), wait_for_status(&kube, &object))
-                    .await
-                    // timeout wraps with another Result
-                    .with_context(|| {
-                        format!(
-                            "timed out waiting for chal {:?} pod {:?} exposed TCP service to become ready",
-                            chal.directory, pod.name
-                        )
-                    })?
-                    // inner result from wait_for_status
-                    .with_context(|| {
-                        format!(
-                            "failed to get status for chal {:?} pod {:?} exposed TCP service",
-                            chal.directory, pod.name
-                        )
-                    })?;
-            }
+    Ok(results)
+}
 
-            // TODO:
-            // expose_results.exposed.push(PodDeployResult::Tcp { port: tcp_ports[0]. });
-        }
-
-        if !http_ports.is_empty() {
-            let http_manifest = minijinja::render!(
-                templates::CHALLENGE_SERVICE_HTTP,
-                chal, pod, http_ports, slug => chal.slugify(), domain => profile.challenges_domain
-            );
-            trace!("HTTP INGRESS:\n{}", http_manifest);
-
-            debug!(
-                "applying http service and ingress for chal {:?} pod {:?}",
-                chal.directory, pod.name
-            );
-            let ingress = apply_manifest_yaml(&kube, &http_manifest).await?;
-            for object in ingress {
-                // wait for objects to be ready, with 5m timeout
-                timeout(Duration::from_secs(5 * 60), wait_for_status(&kube, &object))
-                    .await
-                    // timeout wraps with another Result
-                    .with_context(|| {
-                        format!(
-                            "timed out waiting for chal {:?} pod {:?} ingress to become ready",
-                            chal.directory, pod.name
-                        )
-                    })?
-                    // inner result from wait_for_status
-                    .with_context(|| {
-                        format!(
-                            "failed to get status for chal {:?} pod {:?} ingress",
-                            chal.directory, pod.name
+/// Apply the rendered `manifest` for `chal` and wait for all of its objects to
+/// become ready, for up to `wait_timeout`.
+///
+/// If a Deployment does not become ready in time, the error includes
+/// diagnostics about its pods.
+pub async fn apply_and_wait(
+    kube: &kube::Client,
+    chal: &ChallengeConfig,
+    manifest: &RenderedManifest,
+    wait_timeout: Duration,
+) -> Result<()> {
+    debug!(
+        "applying {} for chal {:?}",
+        manifest.description, chal.directory
+    );
+    let objects = apply_manifest_yaml(kube, &manifest.yaml).await?;
+    for object in objects {
+        match timeout(wait_timeout, wait_for_status(kube, &object)).await {
+            // inner result from wait_for_status
+            Ok(status) => status.with_context(|| {
+                format!(
+                    "failed to get status for chal {:?} {}",
+                    chal.directory, manifest.description
+                )
+            })?,
+
+            // timed out, try to figure out why
+            Err(_) => {
+                let kind = object.types.clone().unwrap_or_default().kind;
+                let diagnostics = match kind.as_str() {
+                    // autoscalers are named the same as the deployment they scale
+                    "Deployment" | "HorizontalPodAutoscaler" => {
+                        diagnostics::deployment_diagnostics(
+                            kube,
+                            &object.namespace().unwrap_or_default(),
+                            &object.name_any(),
                         )
-                    })?;
+                        .await
+                        .unwrap_or_else(|e| format!("could not collect diagnostics: {e:?}"))
+                    }
+                    _ => "no diagnostics available".to_string(),
+                };
+
+                return Err(anyhow!(diagnostics)).with_context(|| {
+                    format!(
+                        "timed out after {:?} waiting for chal {:?} {} to become ready",
+                        wait_timeout, chal.directory, manifest.description
+                    )
+                });
             }
-        }
+        };
     }
 
-    Ok(results)
+    Ok(())
 }
 
 // Updates the current ingress controller chart with the current set of TCP
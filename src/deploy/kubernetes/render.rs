@@ -0,0 +1,744 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use itertools::Itertools;
+use k8s_openapi::api::core::v1::{ConfigMap, Secret};
+use kube::api::ObjectMeta;
+use minijinja;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{debug, info, trace, warn};
+
+use crate::clients::render_strict;
+use crate::configparser::challenge::Pod;
+use crate::configparser::challenge::{EnvValue, ExposeType, FlagType, ListOrMap, VolumeKind};
+use crate::configparser::{enabled_challenges, get_config, get_profile_config, ChallengeConfig};
+
+use super::templates;
+
+/// A rendered manifest for a challenge, in the order it should be applied.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RenderedManifest {
+    /// What this manifest is for, used in logs and errors, e.g. `pod "main" deployment`
+    pub description: String,
+    /// Short name used as the filename when writing to disk, e.g. `main-deployment`
+    pub name: String,
+    /// Rendered (possibly multi-document) yaml
+    pub yaml: String,
+    /// Whether this manifest contains secret values, and should not be kept in
+    /// deploy history
+    #[serde(default)]
+    pub sensitive: bool,
+}
+
+/// A rendered environment variable for a container. Secret values are not
+/// included here, only the key they are stored under in the pod's env Secret.
+#[derive(Debug, Serialize)]
+pub(crate) struct EnvVar {
+    name: String,
+    value: Option<String>,
+    secret_key: Option<String>,
+}
+
+/// A per-team copy of an `instanced` challenge.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Instance {
+    /// Short random id, used to name the instance's resources and hostnames
+    pub id: String,
+    /// Team that owns this instance
+    pub team: String,
+    /// Team name sanitized for use as a label value
+    pub team_label: String,
+    /// When the instance should be garbage collected, in RFC 3339 format
+    pub expires_at: String,
+}
+
+/// Resource limits for a challenge namespace, in Kubernetes quantity notation.
+#[derive(Debug, Serialize)]
+struct Quota {
+    cpu: String,
+    memory: String,
+}
+
+/// Kubernetes' default Deployment `maxSurge`; how many extra pods a rolling
+/// update can start before removing old ones
+const ROLLOUT_SURGE_PERCENT: u64 = 25;
+
+/// Name of the ConfigMap holding the registered template for instanced challenges
+pub const INSTANCE_TEMPLATE_CONFIGMAP: &str = "rcds-instance-template";
+
+/// Stand-in for the flag in registered instance templates
+const REDACTED_FLAG: &str = "(redacted)";
+
+/// Render all Kubernetes manifests for a single challenge `chal`.
+///
+/// Returns the namespace, resource quota, deployments, services, ingresses,
+/// and any extra manifests for the challenge, in the order they need to be applied.
+///
+/// For `instanced` challenges, pods are not rendered; instead the challenge
+/// config is registered in the namespace for the instancer to create copies
+/// of later.
+pub fn render_challenge(
+    profile_name: &str,
+    chal: &ChallengeConfig,
+) -> Result<Vec<RenderedManifest>> {
+    let profile = get_profile_config(profile_name)?;
+    let slug = chal.slugify();
+
+    let mut manifests = vec![];
+
+    let ns_manifest = render_strict(
+        &templates::CHALLENGE_NAMESPACE.load()?,
+        minijinja::context! { chal, slug },
+    )?;
+    trace!("NAMESPACE:\n{}", ns_manifest);
+    manifests.push(RenderedManifest {
+        description: "namespace".to_string(),
+        name: "namespace".to_string(),
+        yaml: ns_manifest,
+        sensitive: false,
+    });
+
+    let quota_manifest = render_strict(
+        &templates::CHALLENGE_QUOTA.load()?,
+        minijinja::context! {
+            slug,
+            quota => challenge_quota(chal)?,
+            defaults => get_config()?.defaults.resources,
+        },
+    )?;
+    trace!("QUOTA:\n{}", quota_manifest);
+    manifests.push(RenderedManifest {
+        description: "resource quota".to_string(),
+        name: "quota".to_string(),
+        yaml: quota_manifest,
+        sensitive: false,
+    });
+
+    // env secrets are shared by all instances, so the instancer never needs
+    // to resolve secret values itself
+    for pod in &chal.pods {
+        if let Some(secret) = render_env_secret(profile_name, chal, pod)
+            .with_context(|| format!("could not render env secret for pod {:?}", pod.name))?
+        {
+            manifests.push(secret);
+        }
+    }
+
+    if chal.instanced {
+        manifests.push(render_instance_template(chal)?);
+    } else {
+        manifests.extend(render_pods(profile_name, chal, None)?);
+    }
+
+    // render any extra manifests shipped with the challenge
+    for path in chal.extra_manifest_paths()? {
+        let template = fs::read_to_string(&path)
+            .with_context(|| format!("could not read extra manifest {path:?}"))?;
+        let manifest = render_strict(
+            &template,
+            minijinja::context! {
+                chal,
+                // extra manifests are rendered once per challenge, so expose
+                // the first pod for the common single-pod case
+                pod => chal.pods.first(),
+                profile_name,
+                slug,
+                domain => profile.challenges_domain
+            },
+        )
+        .with_context(|| format!("could not render extra manifest {path:?}"))?;
+        trace!("EXTRA MANIFEST {:?}:\n{}", path, manifest);
+
+        // strip template extensions for the on-disk name
+        let filename = path.file_name().unwrap().to_string_lossy();
+        let stem = filename
+            .trim_end_matches(".j2")
+            .trim_end_matches(".yaml")
+            .trim_end_matches(".yml");
+        manifests.push(RenderedManifest {
+            description: format!("extra manifest {path:?}"),
+            name: format!("extra-{stem}"),
+            yaml: manifest,
+            sensitive: false,
+        });
+    }
+
+    Ok(manifests)
+}
+
+/// Render the volumes, deployment, services, and ingresses for all pods in
+/// `chal`. If `instance` is set, resources are named and labeled for that
+/// instance.
+pub fn render_pods(
+    profile_name: &str,
+    chal: &ChallengeConfig,
+    instance: Option<&Instance>,
+) -> Result<Vec<RenderedManifest>> {
+    let profile = get_profile_config(profile_name)?;
+    let slug = chal.slugify();
+
+    let mut manifests = vec![];
+
+    // hostnames of pods with internal services, for use in env templates
+    let suffix = instance.map(|i| format!("-{}", i.id)).unwrap_or_default();
+    let services: BTreeMap<_, _> = chal
+        .pods
+        .iter()
+        .filter(|p| {
+            p.all_ports()
+                .any(|port| matches!(port.expose, ExposeType::Internal))
+        })
+        .map(|p| (p.name.clone(), format!("{}{suffix}", p.name)))
+        .collect();
+
+    for pod in &chal.pods {
+        let pod_image = chal.container_tag_for_pod(profile_name, &pod.name)?;
+        let scheduling = pod.scheduling.or(profile.scheduling.get(&chal.category));
+
+        // claims need to exist before the deployment can mount them
+        let persistent_volumes = pod
+            .volumes
+            .iter()
+            .filter(|v| matches!(v.kind, VolumeKind::Persistent { .. }))
+            .collect_vec();
+        if !persistent_volumes.is_empty() {
+            let volumes_manifest = render_strict(
+                &templates::CHALLENGE_VOLUMES.load()?,
+                minijinja::context! { chal, pod, slug, instance, persistent_volumes },
+            )?;
+            trace!("VOLUMES:\n{}", volumes_manifest);
+            manifests.push(RenderedManifest {
+                description: format!("pod {:?} volumes", pod.name),
+                name: format!("{}-volumes", pod.name),
+                yaml: volumes_manifest,
+                sensitive: false,
+            });
+        }
+
+        let sidecar_images = pod
+            .sidecars()
+            .map(|c| {
+                chal.container_tag_for_sidecar(profile_name, &pod.name, &c.name)
+                    .map(|tag| (c.name.clone(), tag))
+            })
+            .collect::<Result<BTreeMap<_, _>>>()?;
+
+        let env = render_env(&pod.name, &pod.env, &services)
+            .with_context(|| format!("could not render env for pod {:?}", pod.name))?;
+        let sidecar_env = pod
+            .sidecars()
+            .map(|c| {
+                render_env(&c.name, &c.env, &services)
+                    .with_context(|| format!("could not render env for container {:?}", c.name))
+                    .map(|env| (c.name.clone(), env))
+            })
+            .collect::<Result<BTreeMap<_, _>>>()?;
+
+        // new instances always start with the current secret values, so only
+        // shared deployments need to be rolled when they change
+        let env_checksum = match instance {
+            None => env_secret_data(profile_name, chal, pod)
+                .and_then(|data| env_secret_checksum(&data))
+                .with_context(|| format!("could not render env secret for pod {:?}", pod.name))?,
+            Some(_) => None,
+        };
+
+        let depl_manifest = render_strict(
+            &templates::CHALLENGE_DEPLOYMENT.load()?,
+            minijinja::context! {
+                chal, pod, pod_image, sidecar_images, env, sidecar_env, profile_name,
+                slug, instance, scheduling, persistent_volumes, env_checksum,
+                env_secret => env_secret_name(&slug, &pod.name),
+            },
+        )?;
+        trace!("DEPLOYMENT:\n{}", depl_manifest);
+        manifests.push(RenderedManifest {
+            description: format!("pod {:?} deployment", pod.name),
+            name: format!("{}-deployment", pod.name),
+            yaml: depl_manifest,
+            sensitive: false,
+        });
+
+        if pod.autoscale.is_some() {
+            let autoscale_manifest = render_strict(
+                &templates::CHALLENGE_AUTOSCALE.load()?,
+                minijinja::context! { chal, pod, slug, instance },
+            )?;
+            trace!("AUTOSCALER:\n{}", autoscale_manifest);
+            manifests.push(RenderedManifest {
+                description: format!("pod {:?} autoscaler", pod.name),
+                name: format!("{}-autoscale", pod.name),
+                yaml: autoscale_manifest,
+                sensitive: false,
+            });
+        }
+
+        // each expose type needs to he handled separately, so separate them by type
+        let tcp_ports = pod
+            .all_ports()
+            .filter(|p| matches!(p.expose, ExposeType::Tcp(_)))
+            .collect_vec();
+        let http_ports = pod
+            .all_ports()
+            .filter(|p| matches!(p.expose, ExposeType::Http(_)))
+            .collect_vec();
+        let internal_ports = pod
+            .all_ports()
+            .filter(|p| matches!(p.expose, ExposeType::Internal))
+            .collect_vec();
+
+        if !internal_ports.is_empty() {
+            let internal_manifest = render_strict(
+                &templates::CHALLENGE_SERVICE_INTERNAL.load()?,
+                minijinja::context! {
+                    chal, pod, internal_ports, tcp_ports, http_ports, slug, instance,
+                },
+            )?;
+            trace!("INTERNAL SERVICE:\n{}", internal_manifest);
+            manifests.push(RenderedManifest {
+                description: format!("pod {:?} internal service", pod.name),
+                name: format!("{}-internal", pod.name),
+                yaml: internal_manifest,
+                sensitive: false,
+            });
+        }
+
+        if !tcp_ports.is_empty() {
+            let tcp_manifest = render_strict(
+                &templates::CHALLENGE_SERVICE_TCP.load()?,
+                minijinja::context! {
+                    chal, pod, tcp_ports, slug, instance, domain => profile.challenges_domain
+                },
+            )?;
+            trace!("TCP SERVICE:\n{}", tcp_manifest);
+            manifests.push(RenderedManifest {
+                description: format!("pod {:?} exposed TCP service", pod.name),
+                name: format!("{}-tcp", pod.name),
+                yaml: tcp_manifest,
+                sensitive: false,
+            });
+        }
+
+        if !http_ports.is_empty() {
+            let http_manifest = render_strict(
+                &templates::CHALLENGE_SERVICE_HTTP.load()?,
+                minijinja::context! {
+                    chal, pod, http_ports, slug, instance, domain => profile.challenges_domain
+                },
+            )?;
+            trace!("HTTP INGRESS:\n{}", http_manifest);
+            manifests.push(RenderedManifest {
+                description: format!("pod {:?} ingress", pod.name),
+                name: format!("{}-http", pod.name),
+                yaml: http_manifest,
+                sensitive: false,
+            });
+        }
+    }
+
+    Ok(manifests)
+}
+
+/// Render the env of `container`. Template values are rendered, e.g.
+/// `http://{{ services.api }}:8080`, plain values are used as-is, and secret
+/// values are replaced with a reference to their key in the pod's env Secret.
+///
+/// `services` maps pod names to the hostname of their internal service.
+pub(crate) fn render_env(
+    container: &str,
+    env: &ListOrMap,
+    services: &BTreeMap<String, String>,
+) -> Result<Vec<EnvVar>> {
+    env_map(env)?
+        .iter()
+        .sorted_by_key(|(name, _)| *name)
+        .map(|(name, value)| match value {
+            EnvValue::Value(v) => Ok(EnvVar {
+                name: name.clone(),
+                value: Some(v.clone()),
+                secret_key: None,
+            }),
+            EnvValue::Template { template } => {
+                render_strict(template, minijinja::context! { services })
+                    .with_context(|| format!("could not render env var {name:?}"))
+                    .map(|v| EnvVar {
+                        name: name.clone(),
+                        value: Some(v),
+                        secret_key: None,
+                    })
+            }
+            _ => Ok(EnvVar {
+                name: name.clone(),
+                value: None,
+                secret_key: Some(env_secret_key(container, name)),
+            }),
+        })
+        .collect()
+}
+
+/// Render the Secret holding the secret env values of all containers in
+/// `pod`, if it has any.
+fn render_env_secret(
+    profile_name: &str,
+    chal: &ChallengeConfig,
+    pod: &Pod,
+) -> Result<Option<RenderedManifest>> {
+    let data = env_secret_data(profile_name, chal, pod)?;
+    if data.is_empty() {
+        return Ok(None);
+    }
+
+    let slug = chal.slugify();
+    let secret = Secret {
+        metadata: ObjectMeta {
+            name: Some(env_secret_name(&slug, &pod.name)),
+            namespace: Some(format!("rcds-{slug}")),
+            annotations: Some(BTreeMap::from([(
+                "app.kubernetes.io/managed-by".to_string(),
+                "rcds".to_string(),
+            )])),
+            ..Default::default()
+        },
+        string_data: Some(data),
+        ..Default::default()
+    };
+
+    // do not trace this, it has secret values in it
+    let yaml = format!("---\n{}", serde_yml::to_string(&secret)?);
+
+    Ok(Some(RenderedManifest {
+        description: format!("pod {:?} env secret", pod.name),
+        name: format!("{}-env-secret", pod.name),
+        yaml,
+        sensitive: true,
+    }))
+}
+
+/// Resolved secret env values of all containers in `pod`, keyed by their key
+/// in the pod's env Secret.
+fn env_secret_data(
+    profile_name: &str,
+    chal: &ChallengeConfig,
+    pod: &Pod,
+) -> Result<BTreeMap<String, String>> {
+    let profile = get_profile_config(profile_name)?;
+
+    let containers =
+        std::iter::once((&pod.name, &pod.env)).chain(pod.sidecars().map(|c| (&c.name, &c.env)));
+
+    let mut data = BTreeMap::new();
+    for (container, env) in containers {
+        for (name, value) in env_map(env)?.iter().filter(|(_, v)| v.is_secret()) {
+            let resolved = match value {
+                EnvValue::Secret { secret } => profile
+                    .secrets
+                    .get(secret)
+                    .cloned()
+                    .ok_or_else(|| anyhow!("secret {secret:?} is not set in profile"))?,
+                EnvValue::Flag { .. } => challenge_flag(chal)?,
+                EnvValue::Encrypted { file, key } => {
+                    decrypt_value(&chal.directory.join(file), key)?
+                }
+                EnvValue::Value(_) | EnvValue::Template { .. } => {
+                    unreachable!("plain values are filtered out")
+                }
+            };
+            data.insert(env_secret_key(container, name), resolved);
+        }
+    }
+
+    Ok(data)
+}
+
+/// Checksum of the env Secret `data` of a pod, if it has any. Pods only read
+/// their env Secret when they start, so this is added to the pod template to
+/// roll the pods whenever a secret value changes.
+fn env_secret_checksum(data: &BTreeMap<String, String>) -> Result<Option<String>> {
+    if data.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(hex::encode(Sha256::digest(serde_json::to_vec(data)?))))
+}
+
+/// Get the parsed env map, since lists are converted to maps when the
+/// challenge is parsed.
+fn env_map(env: &ListOrMap) -> Result<&HashMap<String, EnvValue>> {
+    match env {
+        ListOrMap::Map(m) => Ok(m),
+        ListOrMap::List(_) => bail!("env was not converted to a map"),
+    }
+}
+
+/// Name of the Secret holding secret env values for `pod`.
+fn env_secret_name(slug: &str, pod: &str) -> String {
+    format!("rcds-{slug}-{pod}-env")
+}
+
+/// Key in the env Secret for env var `name` of `container`.
+fn env_secret_key(container: &str, name: &str) -> String {
+    format!("{container}.{name}")
+}
+
+/// The literal flag of `chal`, for passing to pods.
+fn challenge_flag(chal: &ChallengeConfig) -> Result<String> {
+    chal.static_flag()?
+        .ok_or_else(|| anyhow!("challenge flag is not a fixed value and cannot be passed to pods"))
+}
+
+/// Decrypt `key` from the sops-encrypted file at `path`.
+fn decrypt_value(path: &Path, key: &str) -> Result<String> {
+    duct::cmd!("sops", "--decrypt", "--extract", format!("[{key:?}]"), path)
+        .stderr_capture()
+        .read()
+        .with_context(|| format!("could not decrypt {key:?} from {path:?} with sops"))
+}
+
+/// Calculate the namespace ResourceQuota for `chal`: the total resources of
+/// all its pod replicas, plus room for the extra pods started during rolling
+/// updates. If the challenge sets its own `resource_quota`, that is used
+/// instead.
+///
+/// Instanced challenges and challenges without pods get no quota unless one
+/// is set explicitly, since there is nothing to base it on. Neither do
+/// challenges with extra `manifests`, since pods those create are not counted
+/// and would not fit in the calculated quota.
+fn challenge_quota(chal: &ChallengeConfig) -> Result<Option<Quota>> {
+    if let Some(quota) = &chal.resource_quota {
+        return Ok(Some(Quota {
+            cpu: quota.cpu.to_string(),
+            memory: quota.memory.clone(),
+        }));
+    }
+
+    if chal.instanced || chal.pods.is_empty() {
+        return Ok(None);
+    }
+    if !chal.manifests.is_empty() {
+        debug!(
+            "chal {:?} has extra manifests, not calculating its quota",
+            chal.directory
+        );
+        return Ok(None);
+    }
+
+    let defaults = &get_config()?.defaults.resources;
+    let (cpu, memory) = chal
+        .pods
+        .iter()
+        .try_fold((0, 0), |(cpu, memory), pod| -> Result<_> {
+            let (pod_cpu, pod_memory) = pod.resources_per_replica(defaults)?;
+            // rollouts surge by a percentage of replicas, rounded up
+            let replicas = pod.max_replicas().max(0) as u64;
+            let count = replicas + (replicas * ROLLOUT_SURGE_PERCENT).div_ceil(100);
+            Ok((cpu + pod_cpu as u64 * count, memory + pod_memory * count))
+        })?;
+
+    Ok(Some(Quota {
+        cpu: format!("{cpu}m"),
+        memory: format!("{}Mi", memory.div_ceil(1024 * 1024)),
+    }))
+}
+
+/// Render the ConfigMap that registers `chal` as an instanced challenge. This
+/// stores the challenge config as it was deployed, so instances match the
+/// deployed version rather than whatever is in the working copy.
+///
+/// The flag is left out, since instances get it from the shared env Secret.
+/// Secret env values are only stored as references to where their value
+/// comes from, never the value itself.
+pub(crate) fn render_instance_template(chal: &ChallengeConfig) -> Result<RenderedManifest> {
+    let mut template = serde_yml::to_value(chal)?;
+    if let Some(fields) = template.as_mapping_mut() {
+        fields.insert("flag".into(), REDACTED_FLAG.into());
+    }
+
+    let configmap = ConfigMap {
+        metadata: ObjectMeta {
+            name: Some(INSTANCE_TEMPLATE_CONFIGMAP.to_string()),
+            namespace: Some(format!("rcds-{}", chal.slugify())),
+            annotations: Some(BTreeMap::from([(
+                "app.kubernetes.io/managed-by".to_string(),
+                "rcds".to_string(),
+            )])),
+            ..Default::default()
+        },
+        data: Some(BTreeMap::from([(
+            "challenge.yaml".to_string(),
+            serde_yml::to_string(&template)?,
+        )])),
+        ..Default::default()
+    };
+
+    let yaml = format!("---\n{}", serde_yml::to_string(&configmap)?);
+    trace!("INSTANCE TEMPLATE:\n{}", yaml);
+
+    Ok(RenderedManifest {
+        description: "instance template".to_string(),
+        name: "instance-template".to_string(),
+        yaml,
+        sensitive: false,
+    })
+}
+
+/// Render manifests for all enabled challenges in `profile_name` to `out_dir`,
+/// one directory per challenge. Optionally also write Kustomize
+/// `kustomization.yaml` files listing all of the written manifests.
+///
+/// Manifests with secret values are skipped unless `include_secrets` is set,
+/// and renders of challenges that are no longer enabled are removed.
+///
+/// Returns the paths of all written files.
+pub fn write_rendered_challenges(
+    profile_name: &str,
+    out_dir: &Path,
+    kustomize: bool,
+    include_secrets: bool,
+) -> Result<Vec<PathBuf>> {
+    // sort challenges by slug for stable output
+    let chals = enabled_challenges(profile_name)?
+        .into_iter()
+        .sorted_by_key(|c| c.slugify())
+        .collect_vec();
+
+    let mut written = vec![];
+    let mut chal_dirs = vec![];
+
+    for chal in chals {
+        info!("  rendering chal {:?}...", chal.directory);
+        let manifests = render_challenge(profile_name, chal)
+            .with_context(|| format!("could not render chal {:?}", chal.directory))?;
+
+        let slug = chal.slugify();
+        let chal_dir = out_dir.join(&slug);
+
+        // clear out any previous render so removed resources do not linger,
+        // but never anything else that happens to have the same name
+        if chal_dir.exists() {
+            if fs::read_dir(&chal_dir)?.next().is_some() && !is_render_dir(&chal_dir)? {
+                bail!(
+                    "{chal_dir:?} has files that were not rendered by beavercds, not overwriting it. \
+                     Move it out of the way or render to a different directory"
+                );
+            }
+            debug!("removing previous render at {:?}", chal_dir);
+            fs::remove_dir_all(&chal_dir)
+                .with_context(|| format!("could not remove old render dir {chal_dir:?}"))?;
+        }
+        fs::create_dir_all(&chal_dir)
+            .with_context(|| format!("could not create render dir {chal_dir:?}"))?;
+
+        // secrets have to be created some other way, e.g. sealed-secrets
+        let manifests = manifests
+            .into_iter()
+            .filter(|m| {
+                if m.sensitive && !include_secrets {
+                    warn!(
+                        "  skipping {} with secret values (see --include-secrets)",
+                        m.description
+                    );
+                }
+                !m.sensitive || include_secrets
+            })
+            .collect_vec();
+
+        // prefix files with their apply order so they sort the same on disk
+        let filenames = manifests
+            .iter()
+            .enumerate()
+            .map(|(i, m)| format!("{i:02}-{}.yaml", m.name))
+            .collect_vec();
+
+        for (filename, manifest) in filenames.iter().zip(&manifests) {
+            let path = chal_dir.join(filename);
+            trace!("writing {} to {:?}", manifest.description, path);
+            if manifest.sensitive {
+                warn!("{path:?} contains secret values, do not commit it!");
+            }
+            // rendered templates do not keep their trailing newline
+            fs::write(&path, format!("{}\n", manifest.yaml.trim_end()))
+                .with_context(|| format!("could not write manifest {path:?}"))?;
+            written.push(path);
+        }
+
+        if kustomize {
+            written.push(write_kustomization(&chal_dir, &filenames)?);
+        }
+
+        chal_dirs.push(slug);
+    }
+
+    remove_stale_renders(out_dir, &chal_dirs)?;
+
+    if kustomize {
+        written.push(write_kustomization(out_dir, &chal_dirs)?);
+    }
+
+    Ok(written)
+}
+
+/// Remove challenge directories in `out_dir` from previous renders that are
+/// not in `current`, so challenges that are no longer enabled do not keep
+/// getting deployed from it. Only directories that look like a previous render
+/// are removed, anything else in `out_dir` is left alone.
+fn remove_stale_renders(out_dir: &Path, current: &[String]) -> Result<()> {
+    if !out_dir.exists() {
+        return Ok(());
+    }
+
+    for entry in fs::read_dir(out_dir)? {
+        let path = entry?.path();
+        let Some(name) = path.file_name().map(|n| n.to_string_lossy().to_string()) else {
+            continue;
+        };
+        if !path.is_dir() || current.contains(&name) || !is_render_dir(&path)? {
+            continue;
+        }
+
+        info!("  removing render of disabled chal {name:?}");
+        fs::remove_dir_all(&path)
+            .with_context(|| format!("could not remove stale render dir {path:?}"))?;
+    }
+
+    Ok(())
+}
+
+/// Whether `dir` only has files written by `write_rendered_challenges`:
+/// numbered manifests like `00-namespace.yaml` and `kustomization.yaml`.
+pub(crate) fn is_render_dir(dir: &Path) -> Result<bool> {
+    let mut any = false;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        // compare bytes, since the name could have any characters in it
+        let numbered = name.len() > 3
+            && name.as_bytes()[..2].iter().all(u8::is_ascii_digit)
+            && name.as_bytes()[2] == b'-';
+        if !entry.path().is_file()
+            || !(name == "kustomization.yaml" || numbered && name.ends_with(".yaml"))
+        {
+            return Ok(false);
+        }
+        any = true;
+    }
+    Ok(any)
+}
+
+/// Write `kustomization.yaml` in `dir` with `resources` as its resource list.
+fn write_kustomization(dir: &Path, resources: &[String]) -> Result<PathBuf> {
+    let kustomization = serde_yml::to_string(&serde_yml::Mapping::from_iter([
+        (
+            "apiVersion".into(),
+            "kustomize.config.k8s.io/v1beta1".into(),
+        ),
+        ("kind".into(), "Kustomization".into()),
+        ("resources".into(), resources.into()),
+    ]))?;
+
+    let path = dir.join("kustomization.yaml");
+    fs::write(&path, kustomization).with_context(|| format!("could not write {path:?}"))?;
+
+    Ok(path)
+}
@@ -1,13 +1,82 @@
 // Embed Kubernetes template files into binary.
+//
+// Each template can be overridden by placing a file with the same name in the
+// `template_dir` directory set in rcds.yaml.
 
-pub static CHALLENGE_NAMESPACE: &str =
-    include_str!("../../asset_files/challenge_templates/namespace.yaml.j2");
+use std::borrow::Cow;
+use std::fs;
 
-pub static CHALLENGE_DEPLOYMENT: &str =
-    include_str!("../../asset_files/challenge_templates/deployment.yaml.j2");
+use anyhow::{Context, Result};
+use tracing::{debug, trace};
 
-pub static CHALLENGE_SERVICE_HTTP: &str =
-    include_str!("../../asset_files/challenge_templates/http.yaml.j2");
+use crate::configparser::get_config;
 
-pub static CHALLENGE_SERVICE_TCP: &str =
-    include_str!("../../asset_files/challenge_templates/tcp.yaml.j2");
+/// A challenge manifest template, with its embedded default contents.
+pub struct Template {
+    /// Filename of the template, also used to look up any override.
+    pub name: &'static str,
+    /// Built-in template contents.
+    pub builtin: &'static str,
+}
+
+impl Template {
+    /// Return the template contents, preferring a user-provided override from
+    /// the configured template directory over the built-in template.
+    pub fn load(&self) -> Result<Cow<'static, str>> {
+        let template_dir = match &get_config()?.template_dir {
+            Some(d) => d,
+            None => return Ok(Cow::Borrowed(self.builtin)),
+        };
+
+        let override_path = template_dir.join(self.name);
+        if !override_path.exists() {
+            trace!("no override for template {:?}, using builtin", self.name);
+            return Ok(Cow::Borrowed(self.builtin));
+        }
+
+        debug!("using template override {:?}", override_path);
+        fs::read_to_string(&override_path)
+            .map(Cow::Owned)
+            .with_context(|| format!("could not read template override {override_path:?}"))
+    }
+}
+
+pub static CHALLENGE_NAMESPACE: Template = Template {
+    name: "namespace.yaml.j2",
+    builtin: include_str!("../../asset_files/challenge_templates/namespace.yaml.j2"),
+};
+
+pub static CHALLENGE_QUOTA: Template = Template {
+    name: "quota.yaml.j2",
+    builtin: include_str!("../../asset_files/challenge_templates/quota.yaml.j2"),
+};
+
+pub static CHALLENGE_VOLUMES: Template = Template {
+    name: "volumes.yaml.j2",
+    builtin: include_str!("../../asset_files/challenge_templates/volumes.yaml.j2"),
+};
+
+pub static CHALLENGE_DEPLOYMENT: Template = Template {
+    name: "deployment.yaml.j2",
+    builtin: include_str!("../../asset_files/challenge_templates/deployment.yaml.j2"),
+};
+
+pub static CHALLENGE_SERVICE_HTTP: Template = Template {
+    name: "http.yaml.j2",
+    builtin: include_str!("../../asset_files/challenge_templates/http.yaml.j2"),
+};
+
+pub static CHALLENGE_SERVICE_INTERNAL: Template = Template {
+    name: "internal.yaml.j2",
+    builtin: include_str!("../../asset_files/challenge_templates/internal.yaml.j2"),
+};
+
+pub static CHALLENGE_SERVICE_TCP: Template = Template {
+    name: "tcp.yaml.j2",
+    builtin: include_str!("../../asset_files/challenge_templates/tcp.yaml.j2"),
+};
+
+pub static CHALLENGE_AUTOSCALE: Template = Template {
+    name: "autoscale.yaml.j2",
+    builtin: include_str!("../../asset_files/challenge_templates/autoscale.yaml.j2"),
+};
@@ -0,0 +1,231 @@
+// Cluster-wide deploy lock, to keep concurrent deploys from interleaving their
+// applies. Implemented as a Kubernetes Lease in the ingress namespace that is
+// renewed in the background while the deploy is running.
+
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use k8s_openapi::api::coordination::v1::{Lease, LeaseSpec};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::MicroTime;
+use k8s_openapi::chrono::Utc;
+use kube::api::{DeleteParams, ObjectMeta, PostParams};
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info, trace, warn};
+
+use crate::clients::kube_client;
+use crate::cluster_setup::INGRESS_NAMESPACE;
+use crate::configparser::config::ProfileConfig;
+use crate::utils::operator_identity;
+
+/// Name of the Lease object used as the deploy lock
+pub const LOCK_NAME: &str = "beavercds-deploy-lock";
+
+/// How long the lock is valid for without being renewed
+const LEASE_DURATION_SECS: i32 = 60;
+
+/// A held deploy lock. The lease is renewed in the background until released.
+pub struct DeployLock {
+    api: kube::Api<Lease>,
+    holder: String,
+    renewer: JoinHandle<()>,
+    /// Set by the renewer with the reason if the lock could not be renewed
+    lost: Arc<OnceLock<String>>,
+}
+
+/// Acquire the deploy lock for the profile's cluster, failing if someone else
+/// holds an unexpired lock. If `force_unlock` is set, any existing lock is
+/// removed first regardless of who holds it.
+pub async fn acquire(profile: &ProfileConfig, force_unlock: bool) -> Result<DeployLock> {
+    let kube = kube_client(profile).await?;
+    let api: kube::Api<Lease> = kube::Api::namespaced(kube, INGRESS_NAMESPACE);
+
+    // include pid so two deploys from the same user and host still conflict
+    let holder = format!("{} (pid {})", operator_identity(), std::process::id());
+    debug!("acquiring deploy lock as {holder:?}");
+
+    if force_unlock {
+        if let Some(existing) = api.get_opt(LOCK_NAME).await? {
+            warn!(
+                "forcibly removing deploy lock held by {}",
+                holder_of(&existing)
+            );
+            api.delete(LOCK_NAME, &DeleteParams::default()).await?;
+        }
+    }
+
+    let now = MicroTime(Utc::now());
+    let spec = LeaseSpec {
+        holder_identity: Some(holder.clone()),
+        lease_duration_seconds: Some(LEASE_DURATION_SECS),
+        acquire_time: Some(now.clone()),
+        renew_time: Some(now),
+        ..Default::default()
+    };
+
+    match api.get_opt(LOCK_NAME).await? {
+        // no lock yet, take it
+        None => {
+            let lease = Lease {
+                metadata: ObjectMeta {
+                    name: Some(LOCK_NAME.to_string()),
+                    namespace: Some(INGRESS_NAMESPACE.to_string()),
+                    ..Default::default()
+                },
+                spec: Some(spec),
+            };
+            api.create(&PostParams::default(), &lease)
+                .await
+                .map_err(|e| match e {
+                    // someone else created it between our get and create
+                    kube::Error::Api(ae) if ae.code == 409 => {
+                        anyhow!("deploy lock was just acquired by someone else, try again")
+                    }
+                    other => anyhow!(other),
+                })
+                .context("could not create deploy lock")?;
+        }
+
+        // existing lock, only take it over if it has expired
+        Some(existing) => {
+            if !is_expired(&existing) {
+                bail!(
+                    "another deploy is in progress: lock is held by {} (acquired {}, renewed {}). \
+                     If this is stale, rerun with --force-unlock",
+                    holder_of(&existing),
+                    time_of(&existing.spec.as_ref().and_then(|s| s.acquire_time.clone())),
+                    time_of(&existing.spec.as_ref().and_then(|s| s.renew_time.clone())),
+                );
+            }
+
+            warn!(
+                "taking over expired deploy lock from {}",
+                holder_of(&existing)
+            );
+            let transitions = existing
+                .spec
+                .as_ref()
+                .and_then(|s| s.lease_transitions)
+                .unwrap_or(0);
+            let lease = Lease {
+                // keep resourceVersion so this fails if anyone else takes it first
+                metadata: existing.metadata,
+                spec: Some(LeaseSpec {
+                    lease_transitions: Some(transitions + 1),
+                    ..spec
+                }),
+            };
+            api.replace(LOCK_NAME, &PostParams::default(), &lease)
+                .await
+                .map_err(|e| match e {
+                    kube::Error::Api(ae) if ae.code == 409 => {
+                        anyhow!("deploy lock was just acquired by someone else, try again")
+                    }
+                    other => anyhow!(other),
+                })
+                .context("could not take over deploy lock")?;
+        }
+    };
+
+    info!("acquired deploy lock");
+
+    let lost = Arc::new(OnceLock::new());
+    let renewer = tokio::spawn(renew_loop(api.clone(), holder.clone(), lost.clone()));
+
+    Ok(DeployLock {
+        api,
+        holder,
+        renewer,
+        lost,
+    })
+}
+
+impl DeployLock {
+    /// Fail if the lock could not be renewed and may now be held by someone
+    /// else. Check this before changing anything in the cluster.
+    pub fn check(&self) -> Result<()> {
+        match self.lost.get() {
+            Some(reason) => bail!("lost the deploy lock, aborting: {reason}"),
+            None => Ok(()),
+        }
+    }
+
+    /// Stop renewing and remove the lock, if we still hold it.
+    pub async fn release(self) -> Result<()> {
+        self.renewer.abort();
+
+        match self.api.get_opt(LOCK_NAME).await? {
+            Some(lease) if holder_of(&lease) == self.holder => {
+                debug!("releasing deploy lock");
+                self.api.delete(LOCK_NAME, &DeleteParams::default()).await?;
+                Ok(())
+            }
+            Some(lease) => bail!(
+                "deploy lock was taken over by {} before it was released",
+                holder_of(&lease)
+            ),
+            None => {
+                warn!("deploy lock was removed before it was released");
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Periodically bump the lease renew time while we still hold it. If that
+/// fails, the reason is stored in `lost` for the deploy to pick up.
+async fn renew_loop(api: kube::Api<Lease>, holder: String, lost: Arc<OnceLock<String>>) {
+    let interval = Duration::from_secs((LEASE_DURATION_SECS / 3) as u64);
+    loop {
+        tokio::time::sleep(interval).await;
+
+        trace!("renewing deploy lock");
+        let result = async {
+            let mut lease = api.get(LOCK_NAME).await?;
+            if holder_of(&lease) != holder {
+                bail!("lock is now held by {}", holder_of(&lease));
+            }
+            if let Some(spec) = lease.spec.as_mut() {
+                spec.renew_time = Some(MicroTime(Utc::now()));
+            }
+            api.replace(LOCK_NAME, &PostParams::default(), &lease)
+                .await?;
+            Ok(())
+        }
+        .await;
+
+        if let Err(e) = result {
+            error!("could not renew deploy lock: {e:?}");
+            let _ = lost.set(format!("{e:#}"));
+            return;
+        }
+    }
+}
+
+fn holder_of(lease: &Lease) -> String {
+    lease
+        .spec
+        .as_ref()
+        .and_then(|s| s.holder_identity.clone())
+        .unwrap_or_else(|| "(unknown)".to_string())
+}
+
+fn time_of(time: &Option<MicroTime>) -> String {
+    time.as_ref()
+        .map(|t| t.0.to_rfc3339())
+        .unwrap_or_else(|| "(unknown)".to_string())
+}
+
+/// Has the lease gone unrenewed for longer than its duration?
+fn is_expired(lease: &Lease) -> bool {
+    let spec = match &lease.spec {
+        Some(s) => s,
+        None => return true,
+    };
+    let (renewed, duration) = match (&spec.renew_time, spec.lease_duration_seconds) {
+        (Some(r), Some(d)) => (r.0, d),
+        _ => return true,
+    };
+
+    renewed + chrono::Duration::seconds(duration.into()) < Utc::now()
+}
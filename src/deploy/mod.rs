@@ -1,6 +1,8 @@
+pub mod assets;
 pub mod frontend;
 pub mod kubernetes;
-pub mod s3;
+pub mod lock;
+pub mod teardown;
 
 use anyhow::{anyhow, bail, Context, Error, Result};
 use itertools::Itertools;
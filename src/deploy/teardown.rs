@@ -0,0 +1,94 @@
+// Remove everything beavercds deployed for a profile, e.g. after an event is
+// over.
+
+use anyhow::{Context, Result};
+use itertools::Itertools;
+use k8s_openapi::api::core::v1::Namespace;
+use kube::api::{DeleteParams, ListParams};
+use kube::ResourceExt;
+use tracing::{debug, error, info, trace, warn};
+
+use crate::clients::kube_client;
+use crate::configparser::config::ProfileConfig;
+use crate::deploy::assets::{asset_store, AssetStore};
+use crate::deploy::frontend::FrontendApi;
+
+/// Find all challenge namespaces managed by beavercds.
+pub async fn managed_namespaces(profile: &ProfileConfig) -> Result<Vec<String>> {
+    let kube = kube_client(profile).await?;
+    let namespaces: kube::Api<Namespace> = kube::Api::all(kube);
+
+    let managed = namespaces
+        .list_metadata(&ListParams::default())
+        .await?
+        .items
+        .iter()
+        .filter(|ns| {
+            ns.name_any().starts_with("rcds-")
+                && ns
+                    .annotations()
+                    .get("app.kubernetes.io/managed-by")
+                    .is_some_and(|m| m == "rcds")
+        })
+        .map(|ns| ns.name_any())
+        .sorted()
+        .collect_vec();
+
+    trace!("found managed namespaces: {managed:?}");
+    Ok(managed)
+}
+
+/// Delete all challenge namespaces managed by beavercds, and everything in
+/// them. Returns the deleted namespaces.
+pub async fn delete_challenge_namespaces(profile: &ProfileConfig) -> Result<Vec<String>> {
+    let kube = kube_client(profile).await?;
+    let api: kube::Api<Namespace> = kube::Api::all(kube);
+
+    let managed = managed_namespaces(profile).await?;
+    for ns in managed.iter() {
+        info!("  deleting namespace {ns}");
+        api.delete(ns, &DeleteParams::background())
+            .await
+            .with_context(|| format!("could not delete namespace {ns}"))?;
+    }
+
+    Ok(managed)
+}
+
+/// Find all uploaded challenge assets in the profile's asset store.
+pub async fn uploaded_assets(profile: &ProfileConfig) -> Result<Vec<String>> {
+    asset_store(profile)?.list().await
+}
+
+/// Delete all uploaded challenge assets from the asset store. Returns the
+/// deleted paths.
+pub async fn delete_assets(profile: &ProfileConfig) -> Result<Vec<String>> {
+    let store = asset_store(profile)?;
+
+    let paths = store.list().await?;
+    for path in paths.iter() {
+        debug!("  deleting {path}");
+        store.delete(path).await?;
+    }
+
+    Ok(paths)
+}
+
+/// Find all challenges from this repo on the frontend.
+pub async fn frontend_challenges(profile: &ProfileConfig) -> Result<Vec<String>> {
+    FrontendApi::new(profile)?.repo_challenges().await
+}
+
+/// Remove all challenges from this repo from the frontend. Returns the removed
+/// challenge ids.
+pub async fn delete_frontend_challenges(profile: &ProfileConfig) -> Result<Vec<String>> {
+    let api = FrontendApi::new(profile)?;
+
+    let ids = api.repo_challenges().await?;
+    for id in ids.iter() {
+        debug!("  removing chal {id}");
+        api.delete_challenge(id).await?;
+    }
+
+    Ok(ids)
+}
@@ -0,0 +1,315 @@
+// Per-team instances of challenges.
+//
+// Challenges marked `instanced` do not get a shared deployment. Instead, deploy
+// registers the challenge config in its namespace, and teams get their own
+// copy of the challenge pods, services, and ingresses on demand. Each instance
+// has a TTL, after which it is removed by garbage collection.
+
+use std::fmt::Debug;
+use std::iter::repeat_with;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use itertools::Itertools;
+use k8s_openapi::api::apps::v1::Deployment;
+use k8s_openapi::api::autoscaling::v2::HorizontalPodAutoscaler;
+use k8s_openapi::api::core::v1::{ConfigMap, PersistentVolumeClaim, Service};
+use k8s_openapi::api::networking::v1::{Ingress, NetworkPolicy};
+use k8s_openapi::api::policy::v1::PodDisruptionBudget;
+use k8s_openapi::chrono::{DateTime, Utc};
+use k8s_openapi::NamespaceResourceScope;
+use kube::api::{DeleteParams, ListParams};
+use kube::ResourceExt;
+use serde::de::DeserializeOwned;
+use tracing::{debug, info, trace, warn};
+
+use crate::clients::kube_client;
+use crate::configparser::challenge::ExposeType;
+use crate::configparser::{get_challenges, get_profile_config, ChallengeConfig};
+use crate::deploy::kubernetes::render::INSTANCE_TEMPLATE_CONFIGMAP;
+use crate::deploy::kubernetes::{apply_and_wait, render_pods, Instance};
+
+/// A running instance of a challenge.
+#[derive(Debug)]
+pub struct InstanceInfo {
+    /// Namespace of the challenge this is an instance of
+    pub namespace: String,
+    pub instance: Instance,
+    /// Addresses players can reach the instance at. Only filled in when the
+    /// instance is created.
+    pub endpoints: Vec<String>,
+}
+
+/// Create a new instance of challenge `chal_path` for `team`.
+pub async fn create_instance(
+    profile_name: &str,
+    chal_path: &str,
+    team: &str,
+) -> Result<InstanceInfo> {
+    let profile = get_profile_config(profile_name)?;
+    let kube = kube_client(profile).await?;
+    let namespace = namespace_for(chal_path)?;
+
+    let chal = registered_challenge(&kube, &namespace)
+        .await
+        .with_context(|| format!("challenge {chal_path:?} is not deployed as instanced"))?;
+
+    // enforce per-team limit
+    let team_label = sanitize_label(team);
+    let existing = list_instances_in(&kube, Some(&namespace))
+        .await?
+        .into_iter()
+        .filter(|i| i.instance.team_label == team_label)
+        .collect_vec();
+    if existing.len() >= profile.instancer.max_per_team {
+        bail!(
+            "team {team:?} already has {} instance(s) of {chal_path:?} (limit {}): {}",
+            existing.len(),
+            profile.instancer.max_per_team,
+            existing.iter().map(|i| &i.instance.id).join(", ")
+        );
+    }
+
+    let expires_at = Utc::now() + chrono::Duration::seconds(profile.instancer.ttl as i64);
+    let instance = Instance {
+        id: repeat_with(fastrand::lowercase).take(6).collect(),
+        team: team.to_string(),
+        team_label,
+        expires_at: expires_at.to_rfc3339(),
+    };
+    info!(
+        "creating instance {} of {chal_path:?} for team {team:?}...",
+        instance.id
+    );
+
+    let wait_timeout = Duration::from_secs(profile.deploy_timeout);
+    for manifest in render_pods(profile_name, &chal, Some(&instance))? {
+        if let Err(e) = apply_and_wait(&kube, &chal, &manifest, wait_timeout).await {
+            // dont leave a half-created instance around
+            warn!("instance {} failed to start, removing it", instance.id);
+            delete_instance_resources(&kube, &namespace, &instance.id).await?;
+            return Err(e);
+        }
+    }
+
+    Ok(InstanceInfo {
+        namespace,
+        endpoints: instance_endpoints(&chal, &instance, &profile.challenges_domain),
+        instance,
+    })
+}
+
+/// Remove instance `id` of challenge `chal_path`.
+pub async fn delete_instance(profile_name: &str, chal_path: &str, id: &str) -> Result<()> {
+    let profile = get_profile_config(profile_name)?;
+    let kube = kube_client(profile).await?;
+    let namespace = namespace_for(chal_path)?;
+
+    if !list_instances_in(&kube, Some(&namespace))
+        .await?
+        .iter()
+        .any(|i| i.instance.id == id)
+    {
+        bail!("instance {id:?} of challenge {chal_path:?} not found");
+    }
+
+    info!("removing instance {id} of {chal_path:?}...");
+    delete_instance_resources(&kube, &namespace, id).await
+}
+
+/// List all instances of all challenges.
+pub async fn list_instances(profile_name: &str) -> Result<Vec<InstanceInfo>> {
+    let profile = get_profile_config(profile_name)?;
+    let kube = kube_client(profile).await?;
+
+    list_instances_in(&kube, None).await
+}
+
+/// Remove all instances past their expiry time. Returns the removed instances.
+pub async fn collect_garbage(profile_name: &str) -> Result<Vec<InstanceInfo>> {
+    let profile = get_profile_config(profile_name)?;
+    let kube = kube_client(profile).await?;
+
+    let now = Utc::now();
+    let mut removed = vec![];
+    for info in list_instances_in(&kube, None).await? {
+        let expired = DateTime::parse_from_rfc3339(&info.instance.expires_at)
+            .map(|t| t < now)
+            // instances with a broken expiry are not something we can reason
+            // about, so clean them up too
+            .unwrap_or(true);
+
+        if expired {
+            info!(
+                "  removing expired instance {} in {} (team {:?}, expired {})",
+                info.instance.id, info.namespace, info.instance.team, info.instance.expires_at
+            );
+            delete_instance_resources(&kube, &info.namespace, &info.instance.id).await?;
+            removed.push(info);
+        }
+    }
+
+    Ok(removed)
+}
+
+//
+// helpers
+//
+
+/// Fetch the challenge config registered for instancing in `namespace`.
+async fn registered_challenge(kube: &kube::Client, namespace: &str) -> Result<ChallengeConfig> {
+    let api: kube::Api<ConfigMap> = kube::Api::namespaced(kube.clone(), namespace);
+    let configmap = api
+        .get_opt(INSTANCE_TEMPLATE_CONFIGMAP)
+        .await?
+        .ok_or_else(|| anyhow!("no instance template found in namespace {namespace}"))?;
+
+    let chal_yaml = configmap
+        .data
+        .unwrap_or_default()
+        .remove("challenge.yaml")
+        .ok_or_else(|| anyhow!("instance template in namespace {namespace} is empty"))?;
+
+    serde_yml::from_str(&chal_yaml).context("could not parse registered challenge config")
+}
+
+/// Find all instances, either in `namespace` or across the whole cluster.
+async fn list_instances_in(
+    kube: &kube::Client,
+    namespace: Option<&str>,
+) -> Result<Vec<InstanceInfo>> {
+    let api: kube::Api<Deployment> = match namespace {
+        Some(ns) => kube::Api::namespaced(kube.clone(), ns),
+        None => kube::Api::all(kube.clone()),
+    };
+
+    // each pod in a challenge has its own deployment, so dedupe by instance id
+    let instances = api
+        .list_metadata(&ListParams::default().labels("rctf/instance"))
+        .await?
+        .items
+        .into_iter()
+        .map(|depl| {
+            let labels = depl.labels();
+            let annotations = depl.annotations();
+            InstanceInfo {
+                namespace: depl.namespace().unwrap_or_default(),
+                instance: Instance {
+                    id: labels.get("rctf/instance").cloned().unwrap_or_default(),
+                    team: annotations
+                        .get("rctf/instance-team")
+                        .cloned()
+                        .unwrap_or_default(),
+                    team_label: labels
+                        .get("rctf/instance-team")
+                        .cloned()
+                        .unwrap_or_default(),
+                    expires_at: annotations
+                        .get("rctf/expires-at")
+                        .cloned()
+                        .unwrap_or_default(),
+                },
+                endpoints: vec![],
+            }
+        })
+        .unique_by(|i| (i.namespace.clone(), i.instance.id.clone()))
+        .collect_vec();
+
+    trace!("found instances: {instances:?}");
+    Ok(instances)
+}
+
+/// Delete all resources belonging to instance `id` in `namespace`.
+async fn delete_instance_resources(kube: &kube::Client, namespace: &str, id: &str) -> Result<()> {
+    let selector = format!("rctf/instance={id}");
+    debug!("deleting resources in {namespace} matching {selector}");
+
+    delete_labeled::<HorizontalPodAutoscaler>(kube, namespace, &selector).await?;
+    delete_labeled::<PodDisruptionBudget>(kube, namespace, &selector).await?;
+    delete_labeled::<NetworkPolicy>(kube, namespace, &selector).await?;
+    delete_labeled::<Ingress>(kube, namespace, &selector).await?;
+    delete_labeled::<Service>(kube, namespace, &selector).await?;
+    delete_labeled::<Deployment>(kube, namespace, &selector).await?;
+    delete_labeled::<PersistentVolumeClaim>(kube, namespace, &selector).await?;
+
+    Ok(())
+}
+
+/// Delete all `K` objects in `namespace` matching label `selector`.
+async fn delete_labeled<K>(kube: &kube::Client, namespace: &str, selector: &str) -> Result<()>
+where
+    K: kube::Resource<Scope = NamespaceResourceScope> + Clone + DeserializeOwned + Debug,
+    K::DynamicType: Default,
+{
+    let api: kube::Api<K> = kube::Api::namespaced(kube.clone(), namespace);
+    for object in api
+        .list_metadata(&ListParams::default().labels(selector))
+        .await?
+    {
+        trace!(
+            "deleting {} {}",
+            K::kind(&Default::default()),
+            object.name_any()
+        );
+        api.delete(&object.name_any(), &DeleteParams::background())
+            .await?;
+    }
+    Ok(())
+}
+
+/// Addresses that each exposed port of the instance is reachable at, matching
+/// the hosts rendered in the instance's ingresses and services.
+pub(crate) fn instance_endpoints(
+    chal: &ChallengeConfig,
+    instance: &Instance,
+    domain: &str,
+) -> Vec<String> {
+    chal.pods
+        .iter()
+        .flat_map(|pod| pod.all_ports())
+        .filter_map(|port| match &port.expose {
+            ExposeType::Http(subdomain) => {
+                Some(format!("https://{subdomain}-{}.{domain}", instance.id))
+            }
+            ExposeType::Tcp(tcp_port) => Some(format!(
+                "{}-{}.{domain}:{tcp_port}",
+                chal.slugify(),
+                instance.id,
+            )),
+            ExposeType::Internal => None,
+        })
+        .collect()
+}
+
+/// Namespace for the challenge at `chal_path`.
+fn namespace_for(chal_path: &str) -> Result<String> {
+    let chals = get_challenges().map_err(|errs| anyhow!("{errs:?}"))?;
+    let chal = chals
+        .iter()
+        .find(|c| c.directory == Path::new(chal_path))
+        .ok_or_else(|| anyhow!("challenge {chal_path:?} not found"))?;
+
+    Ok(format!("rcds-{}", chal.slugify()))
+}
+
+/// Make `value` safe to use as a Kubernetes label value.
+pub(crate) fn sanitize_label(value: &str) -> String {
+    let sanitized: String = value
+        .to_lowercase()
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | '0'..='9' | '-' | '_' | '.' => c,
+            _ => '-',
+        })
+        .take(63)
+        .collect();
+
+    // labels must start and end with an alphanumeric character
+    let trimmed = sanitized.trim_matches(|c: char| !c.is_ascii_alphanumeric());
+    if trimmed.is_empty() {
+        "team".to_string()
+    } else {
+        trimmed.to_string()
+    }
+}
@@ -9,6 +9,7 @@ pub mod cluster_setup;
 pub mod commands;
 pub mod configparser;
 pub mod deploy;
+pub mod instancer;
 pub mod utils;
 
 #[cfg(test)]
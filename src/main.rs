@@ -68,13 +68,62 @@ fn main() {
             profile,
             no_build,
             dry_run,
+            force_unlock,
+            prune_frontend,
         } => {
             commands::validate::run();
-            commands::deploy::run(profile, no_build, dry_run)
+            commands::deploy::run(profile, no_build, dry_run, force_unlock, prune_frontend)
+        }
+
+        cli::Commands::Instance { command } => match command {
+            cli::InstanceCommands::Create {
+                profile,
+                chal,
+                team,
+            } => commands::instance::create(profile, chal, team),
+            cli::InstanceCommands::Delete { profile, chal, id } => {
+                commands::instance::delete(profile, chal, id)
+            }
+            cli::InstanceCommands::List { profile } => commands::instance::list(profile),
+            cli::InstanceCommands::Gc { profile } => commands::instance::gc(profile),
+        },
+
+        cli::Commands::Rollback { profile, chal, to } => {
+            commands::validate::run();
+            commands::rollback::run(profile, chal, to)
+        }
+
+        cli::Commands::Render {
+            profile,
+            out,
+            kustomize,
+            include_secrets,
+        } => {
+            commands::validate::run();
+            commands::render::run(profile, out, kustomize, include_secrets)
+        }
+
+        cli::Commands::RefreshUrls { profile } => {
+            commands::validate::run();
+            commands::refresh_urls::run(profile)
+        }
+
+        cli::Commands::Teardown {
+            profile,
+            uninstall_charts,
+            yes,
+        } => {
+            commands::validate::run();
+            commands::teardown::run(profile, uninstall_charts, yes)
         }
 
         cli::Commands::ClusterSetup { profile } => {
             commands::cluster_setup::run(profile);
         }
+
+        cli::Commands::BucketSetup { profile } => {
+            commands::validate::run();
+            commands::bucket_setup::run(profile);
+        }
     }
 }
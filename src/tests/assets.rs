@@ -0,0 +1,92 @@
+use std::fs;
+use std::path::PathBuf;
+
+#[cfg(test)]
+use pretty_assertions::assert_eq;
+
+use crate::builder::Asset;
+use crate::deploy::assets::{attachment_disposition, checksum_files, CHECKSUMS_FILENAME};
+
+fn asset(path: &str, sha256: &str, private: bool) -> Asset {
+    Asset {
+        path: PathBuf::from(path),
+        sha256: sha256.to_string(),
+        content_type: None,
+        content_disposition: None,
+        private,
+        url: None,
+    }
+}
+
+#[test]
+/// Public and private files are listed in separate checksum files, sorted
+fn checksum_files_public_private() {
+    let dir = tempfile::tempdir().unwrap();
+    let assets = [
+        asset("misc/foo/zebra.txt", "bbbb", false),
+        asset("misc/foo/dist/handout.zip", "aaaa", false),
+        asset("misc/foo/solve.py", "cccc", true),
+    ];
+
+    let sums = checksum_files(&assets, dir.path()).unwrap();
+
+    assert_eq!(sums.len(), 2);
+    let (public, private) = (&sums[0], &sums[1]);
+
+    assert!(!public.private);
+    assert_eq!(
+        public.path,
+        dir.path().join("public").join(CHECKSUMS_FILENAME)
+    );
+    assert_eq!(
+        fs::read_to_string(&public.path).unwrap(),
+        "aaaa  handout.zip\nbbbb  zebra.txt\n"
+    );
+
+    assert!(private.private);
+    assert_eq!(
+        private.path,
+        dir.path().join("private").join(CHECKSUMS_FILENAME)
+    );
+    assert_eq!(
+        fs::read_to_string(&private.path).unwrap(),
+        "cccc  solve.py\n"
+    );
+
+    // the checksum files are uploaded as assets too, with their own checksum
+    assert_eq!(public.sha256.len(), 64);
+    assert_ne!(public.sha256, private.sha256);
+}
+
+#[test]
+/// No checksum file is written for a visibility without any files
+fn checksum_files_public_only() {
+    let dir = tempfile::tempdir().unwrap();
+    let assets = [asset("misc/foo/handout.zip", "aaaa", false)];
+
+    let sums = checksum_files(&assets, dir.path()).unwrap();
+
+    assert_eq!(sums.len(), 1);
+    assert!(!sums[0].private);
+    assert!(!dir.path().join("private").exists());
+
+    assert!(checksum_files(&[], dir.path()).unwrap().is_empty());
+}
+
+#[test]
+/// Download filenames are quoted safely, with a UTF-8 version for names that
+/// are not plain ASCII
+fn attachment_filenames() {
+    assert_eq!(
+        attachment_disposition("handout.zip"),
+        r#"attachment; filename="handout.zip""#
+    );
+    assert_eq!(
+        attachment_disposition(r#"say "hi"\.txt"#),
+        r#"attachment; filename="say \"hi\"\\.txt""#
+    );
+    assert_eq!(
+        attachment_disposition("flag 🚩.txt"),
+        r#"attachment; filename="flag _.txt"; filename*=UTF-8''flag%20%F0%9F%9A%A9.txt"#
+    );
+}
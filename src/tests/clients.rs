@@ -0,0 +1,30 @@
+use crate::clients::bucket_client;
+use crate::configparser::config::S3Config;
+
+fn s3_config(bucket_name: &str) -> S3Config {
+    S3Config {
+        bucket_name: bucket_name.to_string(),
+        endpoint: "http://localhost:9000".to_string(),
+        region: "x".to_string(),
+        access_key: "key".to_string(),
+        secret_key: "secret".to_string(),
+        content_addressed: false,
+        superseded_grace: 3600,
+        private_url_expiry: 86400,
+        multipart_chunk_size: 64,
+    }
+}
+
+#[test]
+/// Each bucket config gets its own client, and the same config reuses it
+fn bucket_client_per_config() {
+    let first = bucket_client(&s3_config("first")).unwrap();
+    let second = bucket_client(&s3_config("second")).unwrap();
+
+    assert_eq!(first.name, "first");
+    assert_eq!(second.name, "second");
+    assert!(std::ptr::eq(
+        first,
+        bucket_client(&s3_config("first")).unwrap()
+    ));
+}
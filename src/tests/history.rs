@@ -0,0 +1,74 @@
+use std::collections::BTreeMap;
+
+use crate::deploy::kubernetes::history::pin_images;
+use crate::deploy::kubernetes::RenderedManifest;
+
+fn manifest(yaml: &str) -> RenderedManifest {
+    RenderedManifest {
+        description: "test".to_string(),
+        name: "test".to_string(),
+        yaml: yaml.to_string(),
+        sensitive: false,
+    }
+}
+
+#[test]
+/// Images are pinned whether or not the template quoted them
+fn pin_images_unquoted() {
+    let manifests = [manifest(
+        r#"
+apiVersion: apps/v1
+kind: Deployment
+metadata:
+  name: test
+spec:
+  template:
+    spec:
+      initContainers:
+        - name: setup
+          image: "registry.example/chal-setup:testing"
+      containers:
+        - name: main
+          image: registry.example/chal-main:testing
+        - name: upstream
+          image: redis:7
+"#,
+    )];
+    let images = BTreeMap::from([
+        (
+            "registry.example/chal-main:testing".to_string(),
+            "registry.example/chal-main@sha256:aaaa".to_string(),
+        ),
+        (
+            "registry.example/chal-setup:testing".to_string(),
+            "registry.example/chal-setup@sha256:bbbb".to_string(),
+        ),
+    ]);
+
+    let pinned = pin_images(&manifests, &images).unwrap();
+    let yaml = &pinned[0].yaml;
+
+    assert!(yaml.contains("registry.example/chal-main@sha256:aaaa"));
+    assert!(yaml.contains("registry.example/chal-setup@sha256:bbbb"));
+    assert!(yaml.contains("redis:7"));
+    assert!(!yaml.contains(":testing"));
+}
+
+#[test]
+/// Rolling back without pinning anything is an error
+fn pin_images_not_found() {
+    let manifests = [manifest(
+        r#"
+apiVersion: v1
+kind: Namespace
+metadata:
+  name: test
+"#,
+    )];
+    let images = BTreeMap::from([(
+        "registry.example/chal-main:testing".to_string(),
+        "registry.example/chal-main@sha256:aaaa".to_string(),
+    )]);
+
+    assert!(pin_images(&manifests, &images).is_err());
+}
@@ -0,0 +1,116 @@
+use figment::Jail;
+
+#[cfg(test)]
+use pretty_assertions::assert_eq;
+
+use crate::configparser::challenge::{parse_all, FlagType};
+use crate::configparser::ChallengeConfig;
+use crate::deploy::kubernetes::render::render_instance_template;
+use crate::deploy::kubernetes::Instance;
+use crate::instancer::{instance_endpoints, sanitize_label};
+use crate::tests::chal_yaml;
+
+fn instanced_chal() -> String {
+    chal_yaml(
+        r#"
+        instanced: true
+
+        pods:
+            - name: web
+              image: nginx
+              replicas: 1
+              ports:
+                - internal: 80
+                  expose:
+                    http: test
+                - internal: 9000
+                  expose: internal
+
+            - name: shell
+              image: nginx
+              replicas: 1
+              ports:
+                - internal: 1337
+                  expose:
+                    tcp: 31337
+    "#,
+    )
+}
+
+fn instance() -> Instance {
+    Instance {
+        id: "abc123".to_string(),
+        team: "Team Name".to_string(),
+        team_label: "team-name".to_string(),
+        expires_at: "2024-01-01T00:00:00+00:00".to_string(),
+    }
+}
+
+#[test]
+/// Team names are lowercased and anything not allowed in a label is replaced
+fn sanitize_label_characters() {
+    assert_eq!(sanitize_label("Team Name"), "team-name");
+    assert_eq!(sanitize_label("l33t_h4x.0rs"), "l33t_h4x.0rs");
+    assert_eq!(sanitize_label("ünïcödé 🚩"), "n-c-d");
+}
+
+#[test]
+/// Labels must start and end with an alphanumeric character and fit in 63
+/// characters, and are never empty
+fn sanitize_label_bounds() {
+    assert_eq!(sanitize_label("--team--"), "team");
+    assert_eq!(sanitize_label("_.team._"), "team");
+    assert_eq!(sanitize_label(&"a".repeat(100)), "a".repeat(63));
+    // truncating can leave a trailing separator, which is then trimmed
+    assert_eq!(
+        sanitize_label(&format!("{}-b", "a".repeat(62))),
+        "a".repeat(62)
+    );
+    assert_eq!(sanitize_label(""), "team");
+    assert_eq!(sanitize_label("🚩🚩🚩"), "team");
+}
+
+#[test]
+/// Exposed ports get instance-specific hosts, internal ports are skipped
+fn endpoints_per_port() {
+    Jail::expect_with(|jail| {
+        let dir = jail.create_dir("web/test")?;
+        jail.create_file(dir.join("challenge.yaml"), &instanced_chal())?;
+
+        let chals = parse_all().unwrap();
+
+        assert_eq!(
+            instance_endpoints(&chals[0], &instance(), "chals.example.com"),
+            vec![
+                "https://test-abc123.chals.example.com".to_string(),
+                "web-test-abc123.chals.example.com:31337".to_string(),
+            ]
+        );
+
+        Ok(())
+    })
+}
+
+#[test]
+/// The registered template has the challenge config without its flag
+fn instance_template_without_flag() {
+    Jail::expect_with(|jail| {
+        let dir = jail.create_dir("web/test")?;
+        jail.create_file(dir.join("challenge.yaml"), &instanced_chal())?;
+
+        let chals = parse_all().unwrap();
+        let template = render_instance_template(&chals[0]).unwrap();
+
+        assert!(!template.yaml.contains("test{it-works}"));
+
+        // what the instancer reads back still parses as the same challenge
+        let configmap: k8s_openapi::api::core::v1::ConfigMap =
+            serde_yml::from_str(&template.yaml).unwrap();
+        let registered: ChallengeConfig =
+            serde_yml::from_str(&configmap.data.unwrap()["challenge.yaml"]).unwrap();
+        assert_eq!(registered.pods, chals[0].pods);
+        assert!(matches!(registered.flag, FlagType::RawString(_)));
+
+        Ok(())
+    })
+}
@@ -1,4 +1,31 @@
+mod assets;
+mod clients;
+mod history;
+mod instancer;
+mod render;
+
 mod parsing {
     mod challenges;
     mod config;
 }
+
+/// Fields every challenge needs, shared by tests that only care about the rest
+/// of the challenge.
+const CHAL_HEADER: &str = r#"
+    name: testchal
+    author: nobody
+    description: just a test challenge
+    difficulty: 1
+
+    flag:
+        text: test{it-works}
+"#;
+
+/// A `challenge.yaml` with the common fields from [`CHAL_HEADER`] and the
+/// test's own `fields`, which can also override any of the common ones.
+pub(crate) fn chal_yaml(fields: &str) -> String {
+    let mut chal: serde_yml::Mapping = serde_yml::from_str(CHAL_HEADER).unwrap();
+    let fields: serde_yml::Mapping = serde_yml::from_str(fields).unwrap();
+    chal.extend(fields);
+    serde_yml::to_string(&chal).unwrap()
+}
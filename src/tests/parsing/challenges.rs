@@ -6,6 +6,8 @@ use std::path::PathBuf;
 use pretty_assertions::{assert_eq, assert_ne};
 
 use crate::configparser::challenge::*;
+use crate::configparser::config::{Scheduling, Toleration};
+use crate::tests::chal_yaml;
 
 const VALID_CHAL: &str = r#"
     name: testchal
@@ -94,6 +96,9 @@ fn challenge_two_levels() {
 
                 provide: vec![],
                 pods: vec![],
+                instanced: false,
+                resource_quota: None,
+                manifests: vec![],
             }
         );
 
@@ -247,32 +252,53 @@ fn challenge_provide() {
             chals[0].provide,
             vec![
                 ProvideConfig::FromRepo {
-                    files: vec!["foo.txt".into()]
+                    files: vec!["foo.txt".into()],
+                    content_type: None,
+                    content_disposition: None,
+                    private: false,
                 },
                 ProvideConfig::FromRepo {
-                    files: vec!["bar.txt".into(), "baz.txt".into()]
+                    files: vec!["bar.txt".into(), "baz.txt".into()],
+                    content_type: None,
+                    content_disposition: None,
+                    private: false,
                 },
                 ProvideConfig::FromRepoRename {
                     from: "apples".into(),
-                    to: "oranges".into()
+                    to: "oranges".into(),
+                    content_type: None,
+                    content_disposition: None,
+                    private: false,
                 },
                 ProvideConfig::FromRepoArchive {
                     files: vec!["ducks".into(), "beavers".into()],
-                    archive_name: "stuff.zip".into()
+                    archive_name: "stuff.zip".into(),
+                    content_type: None,
+                    content_disposition: None,
+                    private: false,
                 },
                 ProvideConfig::FromContainer {
                     container: "container".to_string(),
-                    files: vec!["/foo/bar".into()]
+                    files: vec!["/foo/bar".into()],
+                    content_type: None,
+                    content_disposition: None,
+                    private: false,
                 },
                 ProvideConfig::FromContainerRename {
                     container: "container".to_string(),
                     from: "/usr/lib/peaches".into(),
                     to: "pears".into(),
+                    content_type: None,
+                    content_disposition: None,
+                    private: false,
                 },
                 ProvideConfig::FromContainerArchive {
                     container: "container".to_string(),
                     files: vec!["/usr/bin/bash".into(), "/usr/bin/zsh".into()],
                     archive_name: "shells.zip".into(),
+                    content_type: None,
+                    content_disposition: None,
+                    private: false,
                 }
             ],
         );
@@ -355,13 +381,20 @@ fn challenge_pods() {
                     name: "foo".to_string(),
                     image_source: ImageSource::Image("nginx".to_string()),
                     replicas: 2,
+                    autoscale: None,
+                    command: vec![],
+                    args: vec![],
+                    workdir: None,
                     env: ListOrMap::Map(HashMap::new()),
                     resources: None,
                     ports: vec![PortConfig {
                         internal: 80,
                         expose: ExposeType::Http("test.chals.example.com".to_string())
                     }],
-                    volume: None
+                    volumes: vec![],
+                    containers: vec![],
+                    init_containers: vec![],
+                    scheduling: Default::default(),
                 },
                 Pod {
                     name: "bar".to_string(),
@@ -371,13 +404,20 @@ fn challenge_pods() {
                         args: HashMap::new()
                     }),
                     replicas: 1,
+                    autoscale: None,
+                    command: vec![],
+                    args: vec![],
+                    workdir: None,
                     env: ListOrMap::Map(HashMap::new()),
                     resources: None,
                     ports: vec![PortConfig {
                         internal: 8000,
                         expose: ExposeType::Tcp(12345)
                     }],
-                    volume: None
+                    volumes: vec![],
+                    containers: vec![],
+                    init_containers: vec![],
+                    scheduling: Default::default(),
                 },
             ]
         );
@@ -440,13 +480,20 @@ fn challenge_pod_build() {
                         args: HashMap::new()
                     }),
                     replicas: 1,
+                    autoscale: None,
+                    command: vec![],
+                    args: vec![],
+                    workdir: None,
                     env: ListOrMap::Map(HashMap::new()),
                     resources: None,
                     ports: vec![PortConfig {
                         internal: 80,
                         expose: ExposeType::Http("test.chals.example.com".to_string())
                     }],
-                    volume: None
+                    volumes: vec![],
+                    containers: vec![],
+                    init_containers: vec![],
+                    scheduling: Default::default(),
                 },
                 Pod {
                     name: "bar".to_string(),
@@ -459,13 +506,20 @@ fn challenge_pod_build() {
                         ])
                     }),
                     replicas: 1,
+                    autoscale: None,
+                    command: vec![],
+                    args: vec![],
+                    workdir: None,
                     env: ListOrMap::Map(HashMap::new()),
                     resources: None,
                     ports: vec![PortConfig {
                         internal: 80,
                         expose: ExposeType::Http("test2.chals.example.com".to_string())
                     }],
-                    volume: None
+                    volumes: vec![],
+                    containers: vec![],
+                    init_containers: vec![],
+                    scheduling: Default::default(),
                 }
             ]
         );
@@ -525,31 +579,45 @@ fn challenge_pod_env() {
 
                     image_source: ImageSource::Image("nginx".to_string()),
                     replicas: 1,
+                    autoscale: None,
+                    command: vec![],
+                    args: vec![],
+                    workdir: None,
                     env: ListOrMap::Map(HashMap::from([
-                        ("FOO".to_string(), "this".to_string()),
-                        ("BAR".to_string(), "that".to_string()),
+                        ("FOO".to_string(), EnvValue::Value("this".to_string())),
+                        ("BAR".to_string(), EnvValue::Value("that".to_string())),
                     ])),
                     resources: None,
                     ports: vec![PortConfig {
                         internal: 80,
                         expose: ExposeType::Http("test.chals.example.com".to_string())
                     }],
-                    volume: None
+                    volumes: vec![],
+                    containers: vec![],
+                    init_containers: vec![],
+                    scheduling: Default::default(),
                 },
                 Pod {
                     name: "bar".to_string(),
                     image_source: ImageSource::Image("nginx".to_string()),
                     replicas: 1,
+                    autoscale: None,
+                    command: vec![],
+                    args: vec![],
+                    workdir: None,
                     env: ListOrMap::Map(HashMap::from([
-                        ("FOO".to_string(), "this".to_string()),
-                        ("BAR".to_string(), "that".to_string()),
+                        ("FOO".to_string(), EnvValue::Value("this".to_string())),
+                        ("BAR".to_string(), EnvValue::Value("that".to_string())),
                     ])),
                     resources: None,
                     ports: vec![PortConfig {
                         internal: 80,
                         expose: ExposeType::Http("test2.chals.example.com".to_string())
                     }],
-                    volume: None
+                    volumes: vec![],
+                    containers: vec![],
+                    init_containers: vec![],
+                    scheduling: Default::default(),
                 }
             ]
         );
@@ -595,3 +663,625 @@ fn challenge_pod_bad_env() {
         Ok(())
     })
 }
+
+#[test]
+/// Challenges can list extra manifest templates to deploy
+fn challenge_manifests() {
+    figment::Jail::expect_with(|jail| {
+        let dir = jail.create_dir("foo/test")?;
+        jail.create_file(
+            dir.join("challenge.yaml"),
+            &chal_yaml(
+                r#"
+                manifests:
+                    - k8s/*.yaml
+                    - extra.yaml.j2
+            "#,
+            ),
+        )?;
+        let k8s = jail.create_dir("foo/test/k8s")?;
+        jail.create_file(k8s.join("b.yaml"), "")?;
+        jail.create_file(k8s.join("a.yaml"), "")?;
+        jail.create_file(dir.join("extra.yaml.j2"), "")?;
+
+        let chals = parse_all().unwrap();
+
+        assert_eq!(
+            chals[0].manifests,
+            vec!["k8s/*.yaml".to_string(), "extra.yaml.j2".to_string()]
+        );
+        assert_eq!(
+            chals[0].extra_manifest_paths().unwrap(),
+            vec![
+                PathBuf::from("foo/test/extra.yaml.j2"),
+                PathBuf::from("foo/test/k8s/a.yaml"),
+                PathBuf::from("foo/test/k8s/b.yaml"),
+            ]
+        );
+
+        Ok(())
+    })
+}
+
+#[test]
+/// Challenges can override their namespace resource quota
+fn challenge_resource_quota() {
+    figment::Jail::expect_with(|jail| {
+        let dir = jail.create_dir("foo/test")?;
+        jail.create_file(
+            dir.join("challenge.yaml"),
+            &chal_yaml(
+                r#"
+                resource_quota:
+                    cpu: 4
+                    memory: 2Gi
+            "#,
+            ),
+        )?;
+
+        let chals = parse_all().unwrap();
+
+        assert_eq!(
+            chals[0].resource_quota,
+            Some(crate::configparser::config::Resource {
+                cpu: 4,
+                memory: "2Gi".to_string()
+            })
+        );
+
+        Ok(())
+    })
+}
+
+#[test]
+/// Pods can autoscale instead of having a fixed replica count
+fn challenge_pod_autoscale() {
+    figment::Jail::expect_with(|jail| {
+        let dir = jail.create_dir("foo/test")?;
+        jail.create_file(
+            dir.join("challenge.yaml"),
+            &chal_yaml(
+                r#"
+                pods:
+                    - name: fixed
+                      image: nginx
+                      ports:
+                        - internal: 80
+                          expose:
+                            http: fixed
+                    - name: scaled
+                      image: nginx
+                      autoscale:
+                        min: 2
+                        max: 10
+                      ports:
+                        - internal: 80
+                          expose:
+                            http: scaled
+            "#,
+            ),
+        )?;
+
+        let chals = parse_all().unwrap();
+
+        assert_eq!(chals[0].pods[0].replicas, 1);
+        assert_eq!(chals[0].pods[0].autoscale, None);
+        assert_eq!(chals[0].pods[0].max_replicas(), 1);
+
+        assert_eq!(
+            chals[0].pods[1].autoscale,
+            Some(Autoscale {
+                min: 2,
+                max: 10,
+                cpu_percent: 80,
+            })
+        );
+        assert_eq!(chals[0].pods[1].max_replicas(), 10);
+
+        Ok(())
+    })
+}
+
+#[test]
+/// Pods can set their own scheduling, falling back to category defaults
+fn challenge_pod_scheduling() {
+    figment::Jail::expect_with(|jail| {
+        let dir = jail.create_dir("pwn/test")?;
+        jail.create_file(
+            dir.join("challenge.yaml"),
+            &chal_yaml(
+                r#"
+                pods:
+                    - name: main
+                      image: nginx
+                      ports: []
+                      node_selector:
+                        pool: pwn
+                      spread: true
+            "#,
+            ),
+        )?;
+
+        let chals = parse_all().unwrap();
+        let pod = &chals[0].pods[0];
+
+        assert_eq!(
+            pod.scheduling,
+            Scheduling {
+                node_selector: Some(HashMap::from([("pool".to_string(), "pwn".to_string())])),
+                tolerations: None,
+                spread: Some(true),
+            }
+        );
+
+        let category_default = Scheduling {
+            node_selector: Some(HashMap::from([("pool".to_string(), "default".to_string())])),
+            tolerations: Some(vec![Toleration {
+                key: Some("gvisor".to_string()),
+                operator: "Exists".to_string(),
+                value: None,
+                effect: Some("NoSchedule".to_string()),
+            }]),
+            spread: Some(false),
+        };
+        assert_eq!(
+            pod.scheduling.or(Some(&category_default)),
+            Scheduling {
+                node_selector: Some(HashMap::from([("pool".to_string(), "pwn".to_string())])),
+                tolerations: category_default.tolerations.clone(),
+                spread: Some(true),
+            }
+        );
+
+        // tolerating every taint needs no key, and none is passed to k8s
+        let tolerate_all: Toleration = serde_yml::from_str("operator: Exists").unwrap();
+        assert_eq!(tolerate_all.key, None);
+        assert_eq!(
+            serde_json::to_value(&tolerate_all).unwrap(),
+            serde_json::json!({ "operator": "Exists" })
+        );
+
+        Ok(())
+    })
+}
+
+#[test]
+/// Pods can mount persistent and ephemeral volumes
+fn challenge_pod_volumes() {
+    figment::Jail::expect_with(|jail| {
+        let dir = jail.create_dir("foo/test")?;
+        jail.create_file(
+            dir.join("challenge.yaml"),
+            &chal_yaml(
+                r#"
+                pods:
+                    - name: main
+                      image: nginx
+                      ports: []
+                      volumes:
+                        - name: data
+                          path: /data
+                          persistent:
+                            size: 1Gi
+                            storage_class: fast
+                        - name: scratch
+                          path: /tmp
+                          ephemeral:
+                            tmpfs: true
+                            size: 64Mi
+            "#,
+            ),
+        )?;
+
+        let chals = parse_all().unwrap();
+
+        assert_eq!(
+            chals[0].pods[0].volumes,
+            vec![
+                Volume {
+                    name: "data".to_string(),
+                    path: "/data".to_string(),
+                    kind: VolumeKind::Persistent {
+                        size: "1Gi".to_string(),
+                        access_mode: "ReadWriteOnce".to_string(),
+                        storage_class: Some("fast".to_string()),
+                    },
+                },
+                Volume {
+                    name: "scratch".to_string(),
+                    path: "/tmp".to_string(),
+                    kind: VolumeKind::Ephemeral {
+                        tmpfs: true,
+                        size: Some("64Mi".to_string()),
+                    },
+                },
+            ]
+        );
+
+        Ok(())
+    })
+}
+
+#[test]
+/// Single-node volumes cannot be used by pods with multiple replicas
+fn challenge_pod_volumes_replicas() {
+    figment::Jail::expect_with(|jail| {
+        let dir = jail.create_dir("foo/test")?;
+        jail.create_file(
+            dir.join("challenge.yaml"),
+            &chal_yaml(
+                r#"
+                pods:
+                    - name: main
+                      image: nginx
+                      replicas: 2
+                      ports: []
+                      volumes:
+                        - name: data
+                          path: /data
+                          persistent:
+                            size: 1Gi
+            "#,
+            ),
+        )?;
+
+        assert!(parse_all().is_err());
+
+        Ok(())
+    })
+}
+
+#[test]
+/// Pods can have additional and init containers
+fn challenge_pod_sidecars() {
+    figment::Jail::expect_with(|jail| {
+        let dir = jail.create_dir("foo/test")?;
+        jail.create_file(
+            dir.join("challenge.yaml"),
+            &chal_yaml(
+                r#"
+                pods:
+                    - name: main
+                      build: .
+                      ports:
+                        - internal: 80
+                          expose:
+                            http: test
+                      containers:
+                        - name: db
+                          image: redis
+                          env:
+                            - FOO=bar
+                        - name: bot
+                          build: bot
+                          ports:
+                            - internal: 8080
+                              expose:
+                                http: test-bot
+                      init_containers:
+                        - name: migrate
+                          build: migrate
+            "#,
+            ),
+        )?;
+
+        let chals = parse_all().unwrap();
+        let pod = &chals[0].pods[0];
+
+        assert_eq!(
+            pod.containers[0],
+            Container {
+                name: "db".to_string(),
+                image_source: ImageSource::Image("redis".to_string()),
+                env: ListOrMap::Map(HashMap::from([(
+                    "FOO".to_string(),
+                    EnvValue::Value("bar".to_string())
+                )])),
+                resources: None,
+                ports: vec![],
+            }
+        );
+        assert_eq!(
+            pod.sidecars().map(|c| c.name.as_str()).collect::<Vec<_>>(),
+            vec!["db", "bot", "migrate"]
+        );
+        assert_eq!(
+            pod.all_ports().map(|p| p.internal).collect::<Vec<_>>(),
+            vec![80, 8080]
+        );
+
+        Ok(())
+    })
+}
+
+#[test]
+/// Container names must be unique within a pod
+fn challenge_pod_sidecars_duplicate_name() {
+    figment::Jail::expect_with(|jail| {
+        let dir = jail.create_dir("foo/test")?;
+        jail.create_file(
+            dir.join("challenge.yaml"),
+            &chal_yaml(
+                r#"
+                pods:
+                    - name: main
+                      image: nginx
+                      ports: []
+                      containers:
+                        - name: main
+                          image: redis
+            "#,
+            ),
+        )?;
+
+        assert!(parse_all().is_err());
+
+        Ok(())
+    })
+}
+
+#[test]
+/// Ports can be exposed only to other pods in the challenge
+fn challenge_pod_internal_port() {
+    figment::Jail::expect_with(|jail| {
+        let dir = jail.create_dir("foo/test")?;
+        jail.create_file(
+            dir.join("challenge.yaml"),
+            &chal_yaml(
+                r#"
+                pods:
+                    - name: api
+                      image: nginx
+                      ports:
+                        - internal: 8080
+                          expose: internal
+            "#,
+            ),
+        )?;
+
+        let chals = parse_all().unwrap();
+
+        assert_eq!(
+            chals[0].pods[0].ports,
+            vec![PortConfig {
+                internal: 8080,
+                expose: ExposeType::Internal,
+            }]
+        );
+
+        Ok(())
+    })
+}
+
+#[test]
+/// Env values can come from profile secrets, the flag, or encrypted files
+fn challenge_pod_env_secrets() {
+    figment::Jail::expect_with(|jail| {
+        let dir = jail.create_dir("foo/test")?;
+        jail.create_file(
+            dir.join("challenge.yaml"),
+            &chal_yaml(
+                r#"
+                pods:
+                    - name: main
+                      image: nginx
+                      ports: []
+                      env:
+                        PLAIN: hello
+                        URL: { template: "http://{{ services.api }}:8080" }
+                        API_KEY: { secret: api-key }
+                        FLAG: { flag: true }
+                        DB_PASS: { file: secrets.enc.yaml, key: db_pass }
+            "#,
+            ),
+        )?;
+
+        let chals = parse_all().unwrap();
+
+        assert_eq!(
+            chals[0].pods[0].env,
+            ListOrMap::Map(HashMap::from([
+                ("PLAIN".to_string(), EnvValue::Value("hello".to_string())),
+                (
+                    "URL".to_string(),
+                    EnvValue::Template {
+                        template: "http://{{ services.api }}:8080".to_string()
+                    }
+                ),
+                (
+                    "API_KEY".to_string(),
+                    EnvValue::Secret {
+                        secret: "api-key".to_string()
+                    }
+                ),
+                ("FLAG".to_string(), EnvValue::Flag { flag: true }),
+                (
+                    "DB_PASS".to_string(),
+                    EnvValue::Encrypted {
+                        file: "secrets.enc.yaml".into(),
+                        key: "db_pass".to_string()
+                    }
+                ),
+            ]))
+        );
+
+        Ok(())
+    })
+}
+
+#[test]
+/// `flag: false` is not a valid env value
+fn challenge_pod_env_flag_false() {
+    figment::Jail::expect_with(|jail| {
+        let dir = jail.create_dir("foo/test")?;
+        jail.create_file(
+            dir.join("challenge.yaml"),
+            &chal_yaml(
+                r#"
+                pods:
+                    - name: main
+                      image: nginx
+                      ports: []
+                      env:
+                        FLAG: { flag: false }
+            "#,
+            ),
+        )?;
+
+        assert!(parse_all().is_err());
+
+        Ok(())
+    })
+}
+
+#[test]
+/// Upstream images can have their entrypoint overridden
+fn challenge_pod_command() {
+    figment::Jail::expect_with(|jail| {
+        let dir = jail.create_dir("foo/test")?;
+        jail.create_file(
+            dir.join("challenge.yaml"),
+            &chal_yaml(
+                r#"
+                pods:
+                    - name: main
+                      image: python:3-alpine
+                      command: [python, -m, http.server]
+                      args: ["8000"]
+                      workdir: /srv
+                      ports: []
+            "#,
+            ),
+        )?;
+
+        let chals = parse_all().unwrap();
+
+        assert_eq!(
+            chals[0].pods[0].command,
+            vec!["python", "-m", "http.server"]
+        );
+        assert_eq!(chals[0].pods[0].args, vec!["8000"]);
+        assert_eq!(chals[0].pods[0].workdir, Some("/srv".to_string()));
+
+        Ok(())
+    })
+}
+
+#[test]
+/// Built images set their entrypoint in the Dockerfile
+fn challenge_pod_command_requires_image() {
+    figment::Jail::expect_with(|jail| {
+        let dir = jail.create_dir("foo/test")?;
+        jail.create_file(
+            dir.join("challenge.yaml"),
+            &chal_yaml(
+                r#"
+                pods:
+                    - name: main
+                      build: .
+                      command: [./server]
+                      ports: []
+            "#,
+            ),
+        )?;
+
+        assert!(parse_all().is_err());
+
+        Ok(())
+    })
+}
+
+#[test]
+/// Provide entries can override the upload headers of their files
+fn challenge_provide_upload_headers() {
+    figment::Jail::expect_with(|jail| {
+        let dir = jail.create_dir("foo/test")?;
+        jail.create_file(
+            dir.join("challenge.yaml"),
+            &chal_yaml(
+                r#"
+                provide:
+                    - include: [notes.md]
+                      content_type: text/plain
+                      content_disposition: inline
+                    - include: [apples]
+                      as: oranges.bin
+                      content_type: application/x-executable
+            "#,
+            ),
+        )?;
+
+        let chals = parse_all().unwrap();
+
+        assert_eq!(
+            chals[0].provide[0].upload_headers(),
+            (Some("text/plain"), Some("inline"))
+        );
+        assert_eq!(
+            chals[0].provide[1].upload_headers(),
+            (Some("application/x-executable"), None)
+        );
+
+        Ok(())
+    })
+}
+
+#[test]
+/// Test that provides can be marked private, and default to public
+fn challenge_provide_private() {
+    figment::Jail::expect_with(|jail| {
+        let dir = jail.create_dir("foo/test")?;
+        jail.create_file(
+            dir.join("challenge.yaml"),
+            &chal_yaml(
+                r#"
+                provide:
+                    - include: [finals.zip]
+                      private: true
+                    - from: container
+                      include: [/chal/binary]
+                      private: true
+                    - public.txt
+            "#,
+            ),
+        )?;
+
+        let chals = parse_all().unwrap();
+
+        assert!(chals[0].provide[0].is_private());
+        assert!(chals[0].provide[1].is_private());
+        assert!(!chals[0].provide[2].is_private());
+
+        Ok(())
+    })
+}
+
+#[test]
+/// Only fixed flags have a literal value to pass on, regex flags do not
+fn challenge_static_flag() {
+    figment::Jail::expect_with(|jail| {
+        let dir = jail.create_dir("foo/fixed")?;
+        jail.create_file(dir.join("challenge.yaml"), VALID_CHAL)?;
+
+        let dir = jail.create_dir("foo/regex")?;
+        jail.create_file(
+            dir.join("challenge.yaml"),
+            &VALID_CHAL.replace("text: test{it-works}", r#"regex: "test\\{.*\\}""#),
+        )?;
+
+        let chals = parse_all().unwrap();
+        let flag_of = |name: &str| {
+            chals
+                .iter()
+                .find(|c| c.directory.ends_with(name))
+                .unwrap()
+                .static_flag()
+                .unwrap()
+        };
+
+        assert_eq!(flag_of("fixed"), Some("test{it-works}".to_string()));
+        assert_eq!(flag_of("regex"), None);
+
+        Ok(())
+    })
+}
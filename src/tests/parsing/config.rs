@@ -1,6 +1,7 @@
 use figment::Jail;
 use std::collections::HashMap;
 use std::fmt::Display;
+use std::path::PathBuf;
 
 #[cfg(test)]
 use pretty_assertions::{assert_eq, assert_ne};
@@ -90,6 +91,7 @@ fn all_yaml() {
                 min: 0,
                 max: 1337,
             }],
+            template_dir: None,
 
             deploy: HashMap::from([(
                 "testing".to_string(),
@@ -108,18 +110,30 @@ fn all_yaml() {
                     challenges_domain: "chals.frontend.example".to_string(),
                     kubeconfig: None,
                     kubecontext: "testcluster".to_string(),
-                    s3: S3Config {
+                    assets: AssetStoreConfig::S3(S3Config {
                         bucket_name: "asset_testing".to_string(),
                         endpoint: "s3.example".to_string(),
                         region: "us-fake-1".to_string(),
                         access_key: "accesskey".to_string(),
                         secret_key: "secretkey".to_string(),
-                    },
+                        content_addressed: false,
+                        superseded_grace: 3600,
+                        private_url_expiry: 86400,
+                        multipart_chunk_size: 64,
+                    }),
                     dns: serde_yml::to_value(HashMap::from([
                         ("provider", "somebody"),
                         ("thing", "whatever"),
                     ]))
                     .unwrap(),
+                    deploy_timeout: 300,
+                    instancer: InstancerConfig {
+                        ttl: 1800,
+                        max_per_team: 1,
+                    },
+                    challenge_budget: None,
+                    scheduling: HashMap::new(),
+                    secrets: HashMap::new(),
                 },
             )]),
         };
@@ -214,6 +228,7 @@ fn registry_tag_format() {
                 min: 0,
                 max: 1337,
             }],
+            template_dir: None,
 
             deploy: HashMap::from([(
                 "testing".to_string(),
@@ -232,18 +247,30 @@ fn registry_tag_format() {
                     challenges_domain: "chals.frontend.example".to_string(),
                     kubeconfig: None,
                     kubecontext: "testcluster".to_string(),
-                    s3: S3Config {
+                    assets: AssetStoreConfig::S3(S3Config {
                         bucket_name: "asset_testing".to_string(),
                         endpoint: "s3.example".to_string(),
                         region: "us-fake-1".to_string(),
                         access_key: "accesskey".to_string(),
                         secret_key: "secretkey".to_string(),
-                    },
+                        content_addressed: false,
+                        superseded_grace: 3600,
+                        private_url_expiry: 86400,
+                        multipart_chunk_size: 64,
+                    }),
                     dns: serde_yml::to_value(HashMap::from([
                         ("provider", "somebody"),
                         ("thing", "whatever"),
                     ]))
                     .unwrap(),
+                    deploy_timeout: 300,
+                    instancer: InstancerConfig {
+                        ttl: 1800,
+                        max_per_team: 1,
+                    },
+                    challenge_budget: None,
+                    scheduling: HashMap::new(),
+                    secrets: HashMap::new(),
                 },
             )]),
         };
@@ -314,6 +341,13 @@ fn yaml_with_env_overrides() {
         jail.set_env("BEAVERCDS_PROFILES_TESTING_FRONTEND_TOKEN", "envtoken");
         jail.set_env("BEAVERCDS_PROFILES_TESTING_S3_ACCESS_KEY", "envkey");
         jail.set_env("BEAVERCDS_PROFILES_TESTING_S3_SECRET_KEY", "envsecret");
+        jail.set_env("BEAVERCDS_PROFILES_TESTING_S3_CONTENT_ADDRESSED", "true");
+        jail.set_env("BEAVERCDS_PROFILES_TESTING_S3_PRIVATE_URL_EXPIRY", "3600");
+        jail.set_env("BEAVERCDS_PROFILES_TESTING_S3_MULTIPART_CHUNK_SIZE", "16");
+        jail.set_env("BEAVERCDS_PROFILES_TESTING_CHALLENGE_BUDGET_CPU", "4");
+        jail.set_env("BEAVERCDS_PROFILES_TESTING_CHALLENGE_BUDGET_MEMORY", "2Gi");
+
+        jail.set_env("BEAVERCDS_TEMPLATE_DIR", "templates");
 
         let config = match parse() {
             Err(e) => Err(figment::Error::from(format!("{:?}", e))),
@@ -325,12 +359,105 @@ fn yaml_with_env_overrides() {
         assert_eq!(config.registry.build.pass, "envbuildpass");
         assert_eq!(config.registry.cluster.user, "envclusteruser");
         assert_eq!(config.registry.cluster.pass, "envclusterpass");
+        assert_eq!(config.template_dir, Some(PathBuf::from("templates")));
 
         let profile = config.profiles.get("testing").unwrap();
 
         assert_eq!(profile.frontend_token, "envtoken");
-        assert_eq!(profile.s3.access_key, "envkey");
-        assert_eq!(profile.s3.secret_key, "envsecret");
+        let AssetStoreConfig::S3(s3) = &profile.assets else {
+            panic!("expected s3 asset store");
+        };
+        assert_eq!(s3.access_key, "envkey");
+        assert_eq!(s3.secret_key, "envsecret");
+        assert!(s3.content_addressed);
+        assert_eq!(s3.private_url_expiry, 3600);
+        assert_eq!(s3.multipart_chunk_size, 16);
+        assert_eq!(
+            profile.challenge_budget,
+            Some(Resource {
+                cpu: 4,
+                memory: "2Gi".to_string(),
+            })
+        );
+
+        Ok(())
+    });
+}
+
+#[test]
+/// Test overriding profile deploy timeout in yaml and envvars
+fn deploy_timeout() {
+    figment::Jail::expect_with(|jail| {
+        jail.clear_env();
+        jail.create_file(
+            "rcds.yaml",
+            r#"
+                flag_regex: test{[a-zA-Z_]+}
+
+                registry:
+                    domain: registry.example/test
+                    build:
+                        user: admin
+                        pass: notrealcreds
+                    cluster:
+                        user: cluster
+                        pass: alsofake
+
+                defaults:
+                    difficulty: 1
+                    resources: { cpu: 1, memory: 500M }
+
+                points:
+                  - difficulty: 1
+                    min: 0
+                    max: 1337
+
+                deploy:
+                    testing:
+                        misc/foo: true
+                    other:
+                        misc/foo: true
+
+                profiles:
+                    testing:
+                        frontend_url: https://frontend.example
+                        frontend_token: secretsecretsecret
+                        challenges_domain: chals.frontend.example
+                        kubecontext: testcluster
+                        deploy_timeout: 60
+                        s3:
+                            bucket_name: asset_testing
+                            endpoint: s3.example
+                            region: us-fake-1
+                            access_key: accesskey
+                            secret_key: secretkey
+                        dns:
+                            provider: somebody
+                    other:
+                        frontend_url: https://frontend.example
+                        frontend_token: secretsecretsecret
+                        challenges_domain: chals.frontend.example
+                        kubecontext: testcluster
+                        s3:
+                            bucket_name: asset_testing
+                            endpoint: s3.example
+                            region: us-fake-1
+                            access_key: accesskey
+                            secret_key: secretkey
+                        dns:
+                            provider: somebody
+            "#,
+        )?;
+
+        jail.set_env("BEAVERCDS_PROFILES_OTHER_DEPLOY_TIMEOUT", "900");
+
+        let config = match parse() {
+            Err(e) => Err(figment::Error::from(format!("{:?}", e))),
+            Ok(config) => Ok(config),
+        }?;
+
+        assert_eq!(config.profiles.get("testing").unwrap().deploy_timeout, 60);
+        assert_eq!(config.profiles.get("other").unwrap().deploy_timeout, 900);
 
         Ok(())
     });
@@ -402,8 +529,11 @@ fn partial_yaml_with_env() {
         let profile = config.profiles.get("testing").unwrap();
 
         assert_eq!(profile.frontend_token, "envtoken");
-        assert_eq!(profile.s3.access_key, "envkey");
-        assert_eq!(profile.s3.secret_key, "envsecret");
+        let AssetStoreConfig::S3(s3) = &profile.assets else {
+            panic!("expected s3 asset store");
+        };
+        assert_eq!(s3.access_key, "envkey");
+        assert_eq!(s3.secret_key, "envsecret");
 
         Ok(())
     });
@@ -483,3 +613,150 @@ fn bad_yaml_missing_secrets() {
         Ok(())
     });
 }
+
+#[test]
+/// Memory quantities in decimal and binary units parse to bytes
+fn memory_quantities() {
+    assert_eq!(parse_memory("1024").unwrap(), 1024);
+    assert_eq!(parse_memory("500M").unwrap(), 500_000_000);
+    assert_eq!(parse_memory("512Mi").unwrap(), 512 * 1024 * 1024);
+    assert_eq!(parse_memory("2Gi").unwrap(), 2 * 1024 * 1024 * 1024);
+    assert_eq!(parse_memory("0.5Gi").unwrap(), 512 * 1024 * 1024);
+    assert_eq!(parse_memory("1.5G").unwrap(), 1_500_000_000);
+    assert_eq!(parse_memory(".25Ki").unwrap(), 256);
+    // fractional bytes round up
+    assert_eq!(parse_memory("0.1").unwrap(), 1);
+    assert!(parse_memory("12 potatoes").is_err());
+    assert!(parse_memory("Mi").is_err());
+    assert!(parse_memory(".Mi").is_err());
+    assert!(parse_memory("1.2.3Gi").is_err());
+}
+
+#[test]
+/// Test using a local directory for assets instead of a bucket
+fn local_asset_store() {
+    figment::Jail::expect_with(|jail| {
+        jail.clear_env();
+        jail.create_file(
+            "rcds.yaml",
+            r#"
+                flag_regex: test{[a-zA-Z_]+}
+
+                registry:
+                    domain: registry.example/test
+                    build:
+                        user: admin
+                        pass: notrealcreds
+                    cluster:
+                        user: cluster
+                        pass: alsofake
+
+                defaults:
+                    difficulty: 1
+                    resources: { cpu: 1, memory: 500M }
+
+                points:
+                  - difficulty: 1
+                    min: 0
+                    max: 1337
+
+                deploy:
+                    testing:
+                        misc/foo: true
+
+                profiles:
+                    testing:
+                        frontend_url: https://frontend.example
+                        frontend_token: secretsecretsecret
+                        challenges_domain: chals.frontend.example
+                        kubecontext: testcluster
+                        local:
+                            path: /srv/assets
+                            base_url: https://files.example
+                        dns:
+                            provider: somebody
+            "#,
+        )?;
+
+        jail.set_env(
+            "BEAVERCDS_PROFILES_TESTING_LOCAL_BASE_URL",
+            "https://cdn.example",
+        );
+
+        let config = match parse() {
+            Err(e) => Err(figment::Error::from(format!("{:?}", e))),
+            Ok(config) => Ok(config),
+        }?;
+
+        assert_eq!(
+            config.profiles.get("testing").unwrap().assets,
+            AssetStoreConfig::Local(LocalAssetConfig {
+                path: "/srv/assets".into(),
+                base_url: "https://cdn.example".to_string(),
+            })
+        );
+
+        Ok(())
+    });
+}
+
+#[test]
+/// Signed urls cannot be valid for longer than S3 allows
+fn private_url_expiry_limit() {
+    figment::Jail::expect_with(|jail| {
+        jail.clear_env();
+        jail.create_file(
+            "rcds.yaml",
+            r#"
+                flag_regex: test{[a-zA-Z_]+}
+
+                registry:
+                    domain: registry.example/test
+                    build:
+                        user: admin
+                        pass: notrealcreds
+                    cluster:
+                        user: cluster
+                        pass: alsofake
+
+                defaults:
+                    difficulty: 1
+                    resources: { cpu: 1, memory: 500M }
+
+                points:
+                  - difficulty: 1
+                    min: 0
+                    max: 1337
+
+                deploy:
+                    testing:
+                        misc/foo: true
+
+                profiles:
+                    testing:
+                        frontend_url: https://frontend.example
+                        frontend_token: secretsecretsecret
+                        challenges_domain: chals.frontend.example
+                        kubecontext: testcluster
+                        s3:
+                            bucket_name: asset_testing
+                            endpoint: s3.example
+                            region: us-fake-1
+                            access_key: accesskey
+                            secret_key: secretkey
+                            private_url_expiry: 604800
+                        dns:
+                            provider: somebody
+            "#,
+        )?;
+
+        // exactly 7 days is allowed
+        assert!(parse().is_ok());
+
+        jail.set_env("BEAVERCDS_PROFILES_TESTING_S3_PRIVATE_URL_EXPIRY", "604801");
+        let err = parse().unwrap_err();
+        assert!(format!("{err:?}").contains("private_url_expiry"));
+
+        Ok(())
+    });
+}
@@ -0,0 +1,184 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+
+use figment::Jail;
+use k8s_openapi::api::apps::v1::Deployment;
+#[cfg(test)]
+use pretty_assertions::{assert_eq, assert_ne};
+
+use crate::configparser::challenge::{parse_all, EnvValue, ListOrMap};
+use crate::configparser::ChallengeConfig;
+use crate::deploy::kubernetes::render::{is_render_dir, render_env, render_pods};
+use crate::tests::chal_yaml;
+
+fn services() -> BTreeMap<String, String> {
+    BTreeMap::from([("api".to_string(), "rcds-misc-foo-api".to_string())])
+}
+
+#[test]
+/// Only values that opt in with `template` are rendered, plain values are
+/// passed through even if they look like templates
+fn env_templates_opt_in() {
+    let env = ListOrMap::Map(HashMap::from([
+        (
+            "PLAIN".to_string(),
+            EnvValue::Value("{{ not a template }}".to_string()),
+        ),
+        (
+            "URL".to_string(),
+            EnvValue::Template {
+                template: "http://{{ services.api }}:8080".to_string(),
+            },
+        ),
+        ("FLAG".to_string(), EnvValue::Flag { flag: true }),
+    ]));
+
+    let rendered = render_env("main", &env, &services()).unwrap();
+
+    assert_eq!(
+        serde_json::to_value(rendered).unwrap(),
+        serde_json::json!([
+            { "name": "FLAG", "value": null, "secret_key": "main.FLAG" },
+            { "name": "PLAIN", "value": "{{ not a template }}", "secret_key": null },
+            { "name": "URL", "value": "http://rcds-misc-foo-api:8080", "secret_key": null },
+        ])
+    );
+}
+
+#[test]
+/// Templates referencing unknown pods fail instead of rendering empty
+fn env_template_unknown_service() {
+    let env = ListOrMap::Map(HashMap::from([(
+        "URL".to_string(),
+        EnvValue::Template {
+            template: "http://{{ services.nope }}:8080".to_string(),
+        },
+    )]));
+
+    assert!(render_env("main", &env, &services()).is_err());
+}
+
+/// Config for tests that render manifests. This is loaded into the global
+/// config once, so every test calling `get_config` must use this same file.
+const RENDER_RCDS: &str = r#"
+    flag_regex: test{[a-zA-Z_]+}
+
+    registry:
+        domain: registry.example/test
+        build: { user: admin, pass: notrealcreds }
+        cluster: { user: cluster, pass: alsofake }
+
+    defaults:
+        difficulty: 1
+        resources: { cpu: 1, memory: 500M }
+
+    points:
+      - difficulty: 1
+        min: 0
+        max: 1337
+
+    deploy:
+        testing:
+            misc/foo: true
+
+    profiles:
+        testing:
+            frontend_url: https://frontend.example
+            frontend_token: secretsecretsecret
+            challenges_domain: chals.frontend.example
+            kubecontext: testcluster
+            local: { path: /srv/assets, base_url: https://files.example }
+            dns: {}
+"#;
+
+fn chal_with_flag(flag: &str) -> String {
+    chal_yaml(&format!(
+        r#"
+        name: foo
+
+        flag:
+            text: {flag}
+
+        pods:
+            - name: main
+              image: nginx
+              replicas: 1
+              ports: []
+              env:
+                PLAIN: hello
+                FLAG: {{ flag: true }}
+        "#
+    ))
+}
+
+/// The `rctf/env-checksum` pod template annotation of the rendered deployment
+/// of `chal`.
+fn env_checksum(chal: &ChallengeConfig) -> Option<String> {
+    let manifests = render_pods("testing", chal, None).unwrap();
+    let deployment = manifests
+        .iter()
+        .find(|m| m.name == "main-deployment")
+        .unwrap();
+    let deployment: Deployment = serde_yml::from_str(&deployment.yaml).unwrap();
+
+    deployment
+        .spec?
+        .template
+        .metadata?
+        .annotations?
+        .get("rctf/env-checksum")
+        .cloned()
+}
+
+#[test]
+/// Changing a value in the env Secret changes the pod template, so running
+/// pods are replaced with ones that read the new value
+fn env_secret_checksum_rollout() {
+    Jail::expect_with(|jail| {
+        jail.clear_env();
+        jail.create_file("rcds.yaml", RENDER_RCDS)?;
+        for (dir, flag) in [
+            ("one", "test{one}"),
+            ("same", "test{one}"),
+            ("two", "test{two}"),
+        ] {
+            let dir = jail.create_dir(format!("misc/{dir}"))?;
+            jail.create_file(dir.join("challenge.yaml"), &chal_with_flag(flag))?;
+        }
+
+        let chals = parse_all().unwrap();
+        let checksum_of =
+            |name: &str| env_checksum(chals.iter().find(|c| c.directory.ends_with(name)).unwrap());
+
+        let one = checksum_of("one");
+        assert!(one.is_some());
+        assert_eq!(one, checksum_of("same"));
+        assert_ne!(one, checksum_of("two"));
+
+        Ok(())
+    })
+}
+
+#[test]
+/// Only directories with nothing but rendered manifests count as renders,
+/// whatever the other files are named
+fn render_dir_detection() {
+    let dir = tempfile::tempdir().unwrap();
+    let render = dir.path().join("render");
+    fs::create_dir(&render).unwrap();
+    fs::write(render.join("00-namespace.yaml"), "").unwrap();
+    fs::write(render.join("kustomization.yaml"), "").unwrap();
+    assert!(is_render_dir(&render).unwrap());
+
+    // multibyte characters around the number prefix must not panic
+    for name in ["a€x.yaml", "0€-x.yaml", "€€€€"] {
+        let other = dir.path().join(format!("other-{name}"));
+        fs::create_dir(&other).unwrap();
+        fs::write(other.join(name), "").unwrap();
+        assert!(!is_render_dir(&other).unwrap(), "{name}");
+    }
+
+    let empty = dir.path().join("empty");
+    fs::create_dir(&empty).unwrap();
+    assert!(!is_render_dir(&empty).unwrap());
+}
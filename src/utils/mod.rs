@@ -1,4 +1,10 @@
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+use anyhow::{Context, Result};
 use futures::{future::try_join_all, TryFuture};
+use sha2::{Digest, Sha256};
 
 /// Helper trait for `Iterator` to add futures::try_await_all() as chain method.
 ///
@@ -26,3 +32,29 @@ where
         try_join_all(self).await
     }
 }
+
+/// Identify who is running beavercds, as `user@host`.
+///
+/// Used to attribute deploys and locks to whoever ran them.
+pub fn operator_identity() -> String {
+    let user = std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_string());
+    let host = std::env::var("HOSTNAME")
+        .ok()
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .map(|h| h.trim().to_string())
+        .filter(|h| !h.is_empty())
+        .unwrap_or_else(|| "unknown".to_string());
+
+    format!("{user}@{host}")
+}
+
+/// Hex-encoded SHA-256 of the file at `path`, read in chunks so large files do
+/// not need to fit in memory.
+pub fn file_sha256(path: &Path) -> Result<String> {
+    let mut file = File::open(path).with_context(|| format!("could not open {path:?}"))?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher).with_context(|| format!("could not read {path:?}"))?;
+    Ok(hex::encode(hasher.finalize()))
+}
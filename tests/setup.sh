@@ -47,6 +47,11 @@ start_stuff (){
   export BEAVERCDS_PROFILES_TESTING_S3_ACCESS_KEY=$(cat $COMPOSE_FILE | yq -r .services.minio.environment.MINIO_ROOT_USER)
   export BEAVERCDS_PROFILES_TESTING_S3_SECRET_KEY=$(cat $COMPOSE_FILE | yq -r .services.minio.environment.MINIO_ROOT_PASSWORD)
 
+  # minio image does not set up default buckets or permissions from envvars,
+  # so create test bucket and allow public downloads once minio is up
+  while ! curl --silent http://localhost:9000/minio/health/live > /dev/null ; do sleep 1 ; done
+  (cd "$(git rev-parse --show-toplevel)/tests/repo" && cargo run -q -- bucket-setup -p testing)
+
   if [ $(exit_cmd) = "exit" ] ; then
     echo
     echo "export these vars manually, or source this script to export"